@@ -0,0 +1,72 @@
+/*
+    Compares the balance policies of AVLTreeSet.
+    Runs the same workloads under each policy and prints the time taken,
+    the rotations made and the shape of the resulting tree.
+    Run with `cargo bench --features stats --bench balance`.
+*/
+
+mod common;
+
+use binary_tree::stats::{counters, reset};
+use binary_tree::{AVLTreeSet, AvlPolicy, BalancePolicy, TreapPolicy, WeightBalancedPolicy};
+
+use common::{random_keys, time};
+
+const ELEMENTS: u64 = 200_000;
+
+/// Runs workload on a fresh set balanced by P and prints one row
+fn bench<P: BalancePolicy + Default>(policy: &str, workload: &str, run: impl Fn(&mut AVLTreeSet<u64, P>)) {
+    let mut set = AVLTreeSet::default();
+    reset();
+    let elapsed = time(|| run(&mut set));
+    let counters = counters();
+    let stats = set.stats();
+
+    println!(
+        "{:<16} {:<12} {:>10.2?} {:>10} {:>8} {:>8.2} {:>8}",
+        policy,
+        workload,
+        elapsed,
+        counters.left_rotations + counters.right_rotations,
+        stats.height,
+        stats.average_depth,
+        stats.optimal_height()
+    );
+}
+
+/// Runs every workload under the policy P
+fn bench_policy<P: BalancePolicy + Default>(policy: &str, keys: &[u64]) {
+    bench::<P>(policy, "random", |set| {
+        for &key in keys {
+            set.insert(key);
+        }
+    });
+
+    bench::<P>(policy, "sequential", |set| {
+        for key in 0..ELEMENTS {
+            set.insert(key);
+        }
+    });
+
+    // Insert everything, then take the first half of the keys again
+    bench::<P>(policy, "churn", |set| {
+        for &key in keys {
+            set.insert(key);
+        }
+        for key in &keys[..keys.len() / 2] {
+            set.take(key);
+        }
+    });
+}
+
+fn main() {
+    let keys = random_keys(ELEMENTS, 0x9e37_79b9_7f4a_7c15);
+
+    println!(
+        "{:<16} {:<12} {:>10} {:>10} {:>8} {:>8} {:>8}",
+        "policy", "workload", "time", "rotations", "height", "avg depth", "optimal"
+    );
+    bench_policy::<AvlPolicy>("AVL", &keys);
+    bench_policy::<WeightBalancedPolicy>("weight-balanced", &keys);
+    bench_policy::<TreapPolicy>("treap", &keys);
+}
@@ -0,0 +1,25 @@
+/*
+    Helpers shared by the benches.
+*/
+
+use std::time::{Duration, Instant};
+
+/// count pseudo random keys below 4 · count from a xorshift generator, so every run with a seed uses the same workload
+pub fn random_keys(count: u64, seed: u64) -> Vec<u64> {
+    let mut state = seed;
+    (0..count)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state % (count * 4)
+        })
+        .collect()
+}
+
+/// Wall clock time taken by f
+pub fn time(f: impl FnOnce()) -> Duration {
+    let start = Instant::now();
+    f();
+    start.elapsed()
+}
@@ -0,0 +1,75 @@
+/*
+    Lookups on a FrozenSet against the AVLTreeSet it was frozen from.
+    Run with `cargo bench --bench frozen`.
+*/
+
+mod common;
+
+use std::hint::black_box;
+
+use binary_tree::AVLTreeSet;
+
+use common::{random_keys, time};
+
+const ELEMENTS: u64 = 1_000_000;
+const LOOKUPS: u64 = 1_000_000;
+
+/// Times one lookup on each probe against both sets and prints one row
+fn row(operation: &str, probes: &[u64], tree: impl Fn(&u64), frozen: impl Fn(&u64)) {
+    let tree = time(|| probes.iter().for_each(&tree));
+    let frozen = time(|| probes.iter().for_each(&frozen));
+
+    println!("{:<12} {:>12.2?} {:>12.2?}", operation, tree, frozen);
+}
+
+fn main() {
+    let set = random_keys(ELEMENTS, 0x2545_f491_4f6c_dd1d)
+        .into_iter()
+        .collect::<AVLTreeSet<_>>();
+    let frozen = set.clone().freeze();
+    let probes = random_keys(LOOKUPS, 0x9e37_79b9_7f4a_7c15);
+
+    println!("{} elements, {} lookups of each kind", set.len(), LOOKUPS);
+    println!("{:<12} {:>12} {:>12}", "operation", "AVLTreeSet", "FrozenSet");
+
+    row(
+        "contains",
+        &probes,
+        |p| {
+            black_box(set.contains(p));
+        },
+        |p| {
+            black_box(frozen.contains(p));
+        },
+    );
+    row(
+        "floor",
+        &probes,
+        |p| {
+            black_box(set.range(..=p).next_back());
+        },
+        |p| {
+            black_box(frozen.floor(p));
+        },
+    );
+    row(
+        "ceiling",
+        &probes,
+        |p| {
+            black_box(set.range(p..).next());
+        },
+        |p| {
+            black_box(frozen.ceiling(p));
+        },
+    );
+    row(
+        "range",
+        &probes,
+        |p| {
+            black_box(set.range(p..&(p + 64)).count());
+        },
+        |p| {
+            black_box(frozen.range(p..&(p + 64)).count());
+        },
+    );
+}
@@ -0,0 +1,72 @@
+/*
+    Benchmark harness for every OrderedSet implementation.
+    Runs the same workloads against each backend and prints a table of timings.
+    Run with `cargo bench`.
+*/
+
+mod common;
+
+use std::collections::BTreeSet;
+use std::hint::black_box;
+
+use binary_tree::{AVLTreeSet, OrderedSet, RedBlackTree};
+
+use common::{random_keys, time};
+
+const ELEMENTS: u64 = 200_000;
+
+/// Times each workload on a fresh set of type S and prints one row
+fn bench<S: OrderedSet<u64>>(name: &str, keys: &[u64]) {
+    let mut set = S::default();
+    let random_insert = time(|| {
+        for &key in keys {
+            set.insert(key);
+        }
+    });
+
+    let mut sequential = S::default();
+    let sequential_insert = time(|| {
+        for key in 0..ELEMENTS {
+            sequential.insert(key);
+        }
+    });
+
+    let contains = time(|| {
+        for key in keys {
+            black_box(set.contains(key));
+        }
+    });
+
+    let range = time(|| {
+        for key in keys.iter().step_by(100) {
+            black_box(set.range(*key..key + 1_000).count());
+        }
+    });
+
+    let iter = time(|| {
+        black_box(set.iter().count());
+    });
+
+    let take = time(|| {
+        for key in keys {
+            black_box(set.take(key));
+        }
+    });
+
+    println!(
+        "{:<16} {:>10.2?} {:>10.2?} {:>10.2?} {:>10.2?} {:>10.2?} {:>10.2?}",
+        name, random_insert, sequential_insert, contains, range, iter, take
+    );
+}
+
+fn main() {
+    let keys = random_keys(ELEMENTS, 0x2545_f491_4f6c_dd1d);
+
+    println!(
+        "{:<16} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10}",
+        "backend", "insert", "seq insert", "contains", "range", "iter", "take"
+    );
+    bench::<AVLTreeSet<u64>>("AVLTreeSet", &keys);
+    bench::<RedBlackTree<u64, ()>>("RedBlackTree", &keys);
+    bench::<BTreeSet<u64>>("BTreeSet", &keys);
+}
@@ -0,0 +1,323 @@
+/*
+    Balancing strategies for AVLTreeSet.
+    Insert and take recompute each node's height and size on the way back
+    up, then hand the node to the set's policy, which restores its own
+    invariant with the node's rotations. Rotations swap values rather than
+    nodes, so the node handed over stays the root of its subtree.
+*/
+
+use std::fmt::Debug;
+
+use crate::{AVLNode, AVLTree, Direction, InvariantError};
+
+/// A rule for keeping an AVLTreeSet balanced.
+///
+/// The set calls the insert or delete hook on every node whose subtree changed,
+/// from the bottom up, after updating the node's height and size.
+pub trait BalancePolicy: Sized {
+    /// Balancing data kept in every node, such as a priority.
+    type Meta: Debug + Clone + PartialEq + Send + Sync;
+
+    /// Metadata for a new leaf.
+    fn leaf(&mut self) -> Self::Meta;
+
+    /// Rebalances node after a value was inserted below it.
+    fn after_insert<T>(&mut self, node: &mut AVLNode<T, (), Self>);
+
+    /// Rebalances node after a value was removed below it.
+    fn after_delete<T>(&mut self, node: &mut AVLNode<T, (), Self>);
+
+    /// Checks that node, found at path, meets the policy's rule, given that its children do.
+    fn check<T>(node: &AVLNode<T, (), Self>, path: &[Direction]) -> Result<(), InvariantError>;
+
+    /// Returns the tree as an AVL tree, if the policy is `AvlPolicy`.
+    ///
+    /// Bulk operations then split and join whole subtrees by height, and
+    /// otherwise insert and take their elements one at a time.
+    fn as_avl<T>(tree: &mut AVLTree<T, (), Self>) -> Option<&mut AVLTree<T>> {
+        let _ = tree;
+        None
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+/// Height balanced: the heights of a node's children differ by at most one.
+pub struct AvlPolicy;
+
+impl BalancePolicy for AvlPolicy {
+    /// Nothing beyond the height every node keeps
+    type Meta = ();
+
+    fn leaf(&mut self) {}
+
+    fn after_insert<T>(&mut self, node: &mut AVLNode<T, (), Self>) {
+        node.rebalance();
+    }
+
+    fn after_delete<T>(&mut self, node: &mut AVLNode<T, (), Self>) {
+        node.rebalance();
+    }
+
+    fn check<T>(node: &AVLNode<T, (), Self>, path: &[Direction]) -> Result<(), InvariantError> {
+        let balance_factor = node.left_height() as isize - node.right_height() as isize;
+        if balance_factor.abs() >= 2 {
+            return Err(InvariantError::Unbalanced {
+                path: path.to_vec(),
+                balance_factor,
+            });
+        }
+
+        Ok(())
+    }
+
+    fn as_avl<T>(tree: &mut AVLTree<T>) -> Option<&mut AVLTree<T>> {
+        Some(tree)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+/// Weight balanced, BB[α]: neither child of a node outweighs the other by more than a factor.
+///
+/// Uses the parameters Δ = 3 and Γ = 2 of Adams' trees, the weight of a subtree
+/// being its size plus one. Needs no metadata beyond the sizes every node keeps.
+pub struct WeightBalancedPolicy;
+
+impl WeightBalancedPolicy {
+    /// Largest allowed ratio between the weights of siblings
+    const DELTA: usize = 3;
+    /// Ratio between the inner and outer grandchild above which a double rotation is needed
+    const GAMMA: usize = 2;
+
+    fn rebalance<T>(node: &mut AVLNode<T, (), Self>) {
+        let (left, right) = (node.left_size() + 1, node.right_size() + 1);
+
+        if left > Self::DELTA * right {
+            let child = node.left_mut().unwrap();
+            if child.right_size() + 1 >= Self::GAMMA * (child.left_size() + 1) {
+                child.rotate_left();
+                count!(double_rotations);
+            } else {
+                count!(single_rotations);
+            }
+            node.rotate_right();
+        } else if right > Self::DELTA * left {
+            let child = node.right_mut().unwrap();
+            if child.left_size() + 1 >= Self::GAMMA * (child.right_size() + 1) {
+                child.rotate_right();
+                count!(double_rotations);
+            } else {
+                count!(single_rotations);
+            }
+            node.rotate_left();
+        }
+    }
+}
+
+impl BalancePolicy for WeightBalancedPolicy {
+    type Meta = ();
+
+    fn leaf(&mut self) {}
+
+    fn after_insert<T>(&mut self, node: &mut AVLNode<T, (), Self>) {
+        Self::rebalance(node);
+    }
+
+    fn after_delete<T>(&mut self, node: &mut AVLNode<T, (), Self>) {
+        Self::rebalance(node);
+    }
+
+    fn check<T>(node: &AVLNode<T, (), Self>, path: &[Direction]) -> Result<(), InvariantError> {
+        let (left, right) = (node.left_size() + 1, node.right_size() + 1);
+        if left > Self::DELTA * right || right > Self::DELTA * left {
+            return Err(InvariantError::PolicyViolated { path: path.to_vec() });
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+/// Treap: every node has a random priority, and no child outranks its parent.
+///
+/// Priorities come from a xorshift generator, so a seed gives the same shapes on every run.
+pub struct TreapPolicy {
+    state: u64,
+}
+
+impl Default for TreapPolicy {
+    fn default() -> Self {
+        Self::with_seed(0x2545_f491_4f6c_dd1d)
+    }
+}
+
+impl TreapPolicy {
+    /// Creates a policy drawing priorities from seed. A seed of zero is replaced, as xorshift would stay at zero.
+    pub fn with_seed(seed: u64) -> Self {
+        Self {
+            state: if seed == 0 { 0x2545_f491_4f6c_dd1d } else { seed },
+        }
+    }
+
+    /// Returns true if either child has a higher priority than node
+    fn outranked<T>(node: &AVLNode<T, (), Self>) -> bool {
+        let priority = |child: Option<&AVLNode<T, (), Self>>| child.map(|child| child.meta);
+        priority(node.left()) > Some(node.meta) || priority(node.right()) > Some(node.meta)
+    }
+
+    /// Rotates the value at node down past every child that outranks it, restoring heap order below node
+    fn sift_down<T>(node: &mut AVLNode<T, (), Self>) {
+        if !Self::outranked(node) {
+            return;
+        }
+
+        let left = node.left().map(|left| left.meta);
+        let right = node.right().map(|right| right.meta);
+        if left > right {
+            node.rotate_right();
+            Self::sift_down(node.right_mut().unwrap());
+        } else {
+            node.rotate_left();
+            Self::sift_down(node.left_mut().unwrap());
+        }
+        node.update_height();
+    }
+}
+
+impl BalancePolicy for TreapPolicy {
+    /// Priority of the node
+    type Meta = u64;
+
+    fn leaf(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+
+    fn after_insert<T>(&mut self, node: &mut AVLNode<T, (), Self>) {
+        // Only the child on the insertion path can outrank node, so one rotation lifts it
+        if node.left().is_some_and(|left| left.meta > node.meta) {
+            node.rotate_right();
+        } else if node.right().is_some_and(|right| right.meta > node.meta) {
+            node.rotate_left();
+        }
+    }
+
+    /// A removed node with two children is replaced by its successor, priority and all,
+    /// which may rank below the children it inherits, so it is rotated down until none outrank it.
+    fn after_delete<T>(&mut self, node: &mut AVLNode<T, (), Self>) {
+        Self::sift_down(node);
+    }
+
+    fn check<T>(node: &AVLNode<T, (), Self>, path: &[Direction]) -> Result<(), InvariantError> {
+        if Self::outranked(node) {
+            return Err(InvariantError::PolicyViolated { path: path.to_vec() });
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AVLTreeSet;
+    use std::collections::BTreeSet;
+
+    /// The one suite every policy must pass: parity with BTreeSet and a valid tree after every change.
+    fn policy_parity<P: BalancePolicy + Default>(xs: Vec<i16>, ys: Vec<i16>, a: i16, b: i16) -> bool {
+        let mut set = AVLTreeSet::<_, P>::default();
+        let mut model = BTreeSet::new();
+        let (low, high) = (a.min(b), a.max(b));
+
+        let inserts = xs
+            .iter()
+            .all(|&x| set.insert(x) == model.insert(x) && set.validate().is_ok());
+        let queries = ys.iter().all(|y| set.contains(y) == model.contains(y))
+            && set.len() == model.len()
+            && set.first() == model.first()
+            && set.last() == model.last()
+            && set.iter().eq(model.iter())
+            && set.range(low..high).eq(model.range(low..high))
+            && set.range(low..=high).rev().eq(model.range(low..=high).rev())
+            && set.range(low..high).len() == model.range(low..high).count();
+        let takes = ys
+            .iter()
+            .all(|y| set.take(y) == model.take(y) && set.validate().is_ok());
+
+        inserts && queries && takes && set.iter().eq(model.iter())
+    }
+
+    #[quickcheck]
+    fn avl_policy_parity(xs: Vec<i16>, ys: Vec<i16>, a: i16, b: i16) -> bool {
+        policy_parity::<AvlPolicy>(xs, ys, a, b)
+    }
+
+    #[quickcheck]
+    fn weight_balanced_policy_parity(xs: Vec<i16>, ys: Vec<i16>, a: i16, b: i16) -> bool {
+        policy_parity::<WeightBalancedPolicy>(xs, ys, a, b)
+    }
+
+    #[quickcheck]
+    fn treap_policy_parity(xs: Vec<i16>, ys: Vec<i16>, a: i16, b: i16) -> bool {
+        policy_parity::<TreapPolicy>(xs, ys, a, b)
+    }
+
+    /// Inserts 0..n in order, the worst case for an unbalanced tree, then removes every other value.
+    fn sequential<P: BalancePolicy + Default>(n: u32) -> AVLTreeSet<u32, P> {
+        let mut set = (0..n).collect::<AVLTreeSet<_, P>>();
+        for x in (0..n).step_by(2) {
+            set.take(&x);
+        }
+        set
+    }
+
+    /// Number of levels in the tree
+    fn height<P: BalancePolicy>(set: &AVLTreeSet<u32, P>) -> usize {
+        set.root.as_ref().map_or(0, |node| node.height())
+    }
+
+    #[test]
+    fn policies_stay_shallow() {
+        let avl = sequential::<AvlPolicy>(4096);
+        let weight_balanced = sequential::<WeightBalancedPolicy>(4096);
+        let treap = sequential::<TreapPolicy>(4096);
+
+        // A perfect tree of 2048 nodes has 11 levels, and the worst AVL and
+        // weight balanced trees log2(n) / log2(φ) and log2(n) / log2(4/3) levels
+        assert!(height(&avl) <= 16);
+        assert!(height(&weight_balanced) <= 26);
+        // The treap's height is random, and is expected to be near 4.3·ln(n), some 33 levels
+        assert!(height(&treap) <= 40);
+
+        assert_eq!(avl.len(), 2048);
+        assert!(avl.iter().copied().eq((1..4096).step_by(2)));
+        assert!(avl.iter().eq(weight_balanced.iter()) && avl.iter().eq(treap.iter()));
+    }
+
+    #[quickcheck]
+    fn treap_priorities_follow_keys(xs: Vec<i16>, ys: Vec<i16>) -> bool {
+        let mut set = xs.into_iter().collect::<AVLTreeSet<_, TreapPolicy>>();
+        let priorities = |set: &AVLTreeSet<i16, TreapPolicy>| {
+            set.node_iter().map(|node| (node.value, node.meta)).collect::<Vec<_>>()
+        };
+        let before = priorities(&set);
+
+        for y in &ys {
+            set.take(y);
+        }
+
+        // Every remaining key keeps the priority it was given when inserted
+        let after = priorities(&set);
+        set.validate().is_ok() && after.iter().all(|entry| before.contains(entry))
+    }
+
+    #[test]
+    fn detects_policy_violation() {
+        let mut set = (0..8).collect::<AVLTreeSet<_, TreapPolicy>>();
+        assert!(set.validate().is_ok());
+
+        set.root.as_mut().unwrap().meta = 0;
+        assert_eq!(set.validate(), Err(InvariantError::PolicyViolated { path: vec![] }));
+    }
+}
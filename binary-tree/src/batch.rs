@@ -0,0 +1,377 @@
+/*
+    Batch insertion and removal of sorted runs.
+    The batch is split around each node it meets on the way down, so every
+    key continues from where its neighbours left off instead of from the root,
+    and the subtrees are joined back together on the way up.
+    A batch of m keys into n elements takes O(m log(n / m + 1)).
+
+    Other policies than AvlPolicy cannot join trees, so they find the position
+    of every key first and then insert or take them one at a time.
+
+    Every split or position is planned on the borrowed tree before it is
+    changed, so a panicking comparison leaves the set as it was. Dropping the
+    elements a batch replaces or removes waits until the tree is whole again.
+*/
+
+use std::borrow::Borrow;
+
+use crate::ops::{build_from, get_at, join, join2};
+use crate::{AVLNode, AVLTree, AVLTreeSet, BalancePolicy};
+
+/// Where a batch splits around a node: the number of its values below the node,
+/// and whether the next one is equal to it.
+type Split = (usize, bool);
+
+impl<T: Ord, P: BalancePolicy> AVLTreeSet<T, P> {
+    /// Adds every value in the batch, returning how many were not already present.
+    ///
+    /// The batch should be in ascending order, otherwise it is sorted first.
+    /// Of equal values only the first is inserted.
+    /// Comparisons are all made before the tree is changed, so if one panics the set is left as it was.
+    pub fn insert_sorted_batch<I: IntoIterator<Item = T>>(&mut self, values: I) -> usize {
+        let mut values = values.into_iter().collect::<Vec<_>>();
+        if !values.windows(2).all(|pair| pair[0] < pair[1]) {
+            values.sort();
+            values.dedup();
+        }
+
+        // Values equal to an element are rejected, to be dropped once the tree is whole
+        let (inserted, rejected) = if let Some(root) = P::as_avl(&mut self.root) {
+            let mut splits = Vec::new();
+            plan(root, &values, &|value, node| value < node, &|value, node| value == node, &mut splits);
+
+            let mut values = values.into_iter().map(Some).collect::<Vec<_>>();
+            let mut inserted = 0;
+            *root = union(root.take(), &mut values, &mut splits.into_iter(), &mut inserted);
+            self.len += inserted;
+
+            (inserted, values.into_iter().flatten().collect())
+        } else {
+            let places = values
+                .iter()
+                .map(|value| {
+                    let rank = self.rank(|element| element < value);
+                    (rank, get_at(&self.root, rank).is_some_and(|node| &node.value == value))
+                })
+                .collect::<Vec<_>>();
+
+            // Each value inserted before moves the next one's position up by one
+            let mut inserted = 0;
+            let mut rejected = Vec::new();
+            for (value, (rank, found)) in values.into_iter().zip(places) {
+                if found {
+                    rejected.push(value);
+                } else {
+                    self.insert_at(rank + inserted, value);
+                    inserted += 1;
+                }
+            }
+
+            (inserted, rejected)
+        };
+
+        #[cfg(feature = "debug-invariants")]
+        self.assert_invariants();
+
+        drop(rejected);
+        inserted
+    }
+
+    /// Removes every element equal to a value in the batch, returning how many were removed.
+    ///
+    /// The batch should be in ascending order, otherwise it is sorted first.
+    /// Comparisons are all made before the tree is changed, so if one panics the set is left as it was.
+    pub fn remove_sorted_batch<'a, Q, I>(&mut self, values: I) -> usize
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized + 'a,
+        I: IntoIterator<Item = &'a Q>,
+    {
+        let mut values = values.into_iter().collect::<Vec<_>>();
+        if !values.windows(2).all(|pair| pair[0] < pair[1]) {
+            values.sort();
+            values.dedup();
+        }
+
+        let removed = if let Some(root) = P::as_avl(&mut self.root) {
+            let mut splits = Vec::new();
+            plan(
+                root,
+                &values,
+                &|value, node: &T| *value < node.borrow(),
+                &|value, node: &T| *value == node.borrow(),
+                &mut splits,
+            );
+
+            let mut removed = Vec::new();
+            *root = difference(root.take(), values.len(), &mut splits.into_iter(), &mut removed);
+            self.len -= removed.len();
+
+            removed.into_iter().map(|node| node.value).collect()
+        } else {
+            let ranks = values
+                .iter()
+                .filter_map(|value| {
+                    let rank = self.rank(|element| element.borrow() < *value);
+                    get_at(&self.root, rank)
+                        .filter(|node| node.value.borrow() == *value)
+                        .map(|_| rank)
+                })
+                .collect::<Vec<_>>();
+
+            // Each element taken before moves the next one's position down by one
+            ranks
+                .into_iter()
+                .enumerate()
+                .map(|(taken, rank)| self.take_at(rank - taken).unwrap())
+                .collect::<Vec<_>>()
+        };
+
+        #[cfg(feature = "debug-invariants")]
+        self.assert_invariants();
+
+        removed.len()
+    }
+}
+
+/// Records in pre-order how the strictly ascending values split around every node
+/// the batch operations visit, without changing the tree.
+fn plan<T, V>(
+    tree: &AVLTree<T>,
+    values: &[V],
+    less: &impl Fn(&V, &T) -> bool,
+    equal: &impl Fn(&V, &T) -> bool,
+    splits: &mut Vec<Split>,
+) {
+    let Some(node) = tree.as_ref().filter(|_| !values.is_empty()) else {
+        return;
+    };
+
+    let below = values.partition_point(|value| less(value, &node.value));
+    let found = values.get(below).is_some_and(|value| equal(value, &node.value));
+    splits.push((below, found));
+
+    plan(&node.left, &values[..below], less, equal, splits);
+    plan(&node.right, &values[below + found as usize..], less, equal, splits);
+}
+
+/// Adds the planned values to the tree, counting those that were new.
+fn union<T>(
+    tree: AVLTree<T>,
+    values: &mut [Option<T>],
+    splits: &mut impl Iterator<Item = Split>,
+    inserted: &mut usize,
+) -> AVLTree<T> {
+    if values.is_empty() {
+        return tree;
+    }
+
+    let Some(mut node) = tree else {
+        *inserted += values.len();
+        let len = values.len();
+        return build_from(&mut values.iter_mut().map(|value| value.take().unwrap()), len);
+    };
+
+    let (below, found) = splits.next().unwrap();
+    let (values, above) = values.split_at_mut(below);
+
+    let left = union(node.left.take(), values, splits, inserted);
+    let right = union(node.right.take(), &mut above[found as usize..], splits, inserted);
+    Some(join(left, node, right))
+}
+
+/// Removes the planned values from the tree, collecting the nodes that held them.
+fn difference<T>(
+    tree: AVLTree<T>,
+    values: usize,
+    splits: &mut impl Iterator<Item = Split>,
+    removed: &mut Vec<Box<AVLNode<T>>>,
+) -> AVLTree<T> {
+    if values == 0 {
+        return tree;
+    }
+
+    let mut node = tree?;
+    let (below, found) = splits.next().unwrap();
+
+    let left = difference(node.left.take(), below, splits, removed);
+    let right = difference(node.right.take(), values - below - found as usize, splits, removed);
+    if found {
+        removed.push(node);
+        join2(left, right)
+    } else {
+        Some(join(left, node, right))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AvlPolicy, TreapPolicy, WeightBalancedPolicy};
+    use crate::tests::{dropped_once, values, Bomb, DROPS, DROP_FUSE, FUSE};
+    use std::collections::BTreeSet;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    fn insert_batch<P: BalancePolicy + Default>(xs: Vec<u16>, batch: Vec<u16>, sort: bool) -> bool {
+        let mut set = xs.iter().cloned().collect::<AVLTreeSet<_, P>>();
+        let mut btree_set = xs.into_iter().collect::<BTreeSet<_>>();
+
+        let mut batch = batch;
+        if sort {
+            batch.sort();
+        }
+
+        let expected = batch.iter().filter(|&&x| btree_set.insert(x)).count();
+
+        set.insert_sorted_batch(batch) == expected
+            && set.len() == btree_set.len()
+            && set.validate().is_ok()
+            && set.iter().eq(btree_set.iter())
+    }
+
+    #[quickcheck]
+    fn insert_batch_parity(xs: Vec<u16>, batch: Vec<u16>, sort: bool) -> bool {
+        insert_batch::<AvlPolicy>(xs, batch, sort)
+    }
+
+    #[quickcheck]
+    fn weight_balanced_insert_batch_parity(xs: Vec<u16>, batch: Vec<u16>, sort: bool) -> bool {
+        insert_batch::<WeightBalancedPolicy>(xs, batch, sort)
+    }
+
+    #[quickcheck]
+    fn treap_insert_batch_parity(xs: Vec<u16>, batch: Vec<u16>, sort: bool) -> bool {
+        insert_batch::<TreapPolicy>(xs, batch, sort)
+    }
+
+    fn remove_batch<P: BalancePolicy + Default>(xs: Vec<u16>, batch: Vec<u16>, sort: bool) -> bool {
+        let mut set = xs.iter().cloned().collect::<AVLTreeSet<_, P>>();
+        let mut btree_set = xs.into_iter().collect::<BTreeSet<_>>();
+
+        let mut batch = batch;
+        if sort {
+            batch.sort();
+        }
+
+        let expected = batch.iter().filter(|x| btree_set.remove(x)).count();
+
+        set.remove_sorted_batch(&batch) == expected
+            && set.len() == btree_set.len()
+            && set.validate().is_ok()
+            && set.iter().eq(btree_set.iter())
+    }
+
+    #[quickcheck]
+    fn remove_batch_parity(xs: Vec<u16>, batch: Vec<u16>, sort: bool) -> bool {
+        remove_batch::<AvlPolicy>(xs, batch, sort)
+    }
+
+    #[quickcheck]
+    fn weight_balanced_remove_batch_parity(xs: Vec<u16>, batch: Vec<u16>, sort: bool) -> bool {
+        remove_batch::<WeightBalancedPolicy>(xs, batch, sort)
+    }
+
+    #[quickcheck]
+    fn treap_remove_batch_parity(xs: Vec<u16>, batch: Vec<u16>, sort: bool) -> bool {
+        remove_batch::<TreapPolicy>(xs, batch, sort)
+    }
+
+    fn batch_atomic<P: BalancePolicy + Default>(xs: Vec<u8>, batches: Vec<(bool, Vec<u8>, u8)>) -> bool {
+        DROPS.take();
+        let mut ids = 0;
+        let mut set = AVLTreeSet::<_, P>::default();
+        set.insert_sorted_batch(xs.into_iter().map(|x| Bomb::new(x % 64, &mut ids)));
+
+        let consistent = batches.into_iter().all(|(insert, batch, fuse)| {
+            let before = values(&set).into_iter().collect::<BTreeSet<_>>();
+            let mut after = before.clone();
+            let batch = batch.into_iter().map(|x| Bomb::new(x % 64, &mut ids)).collect::<Vec<_>>();
+
+            FUSE.set(Some(fuse));
+            let result = catch_unwind(AssertUnwindSafe(|| {
+                if insert {
+                    after.extend(batch.iter().map(|bomb| bomb.value));
+                    set.insert_sorted_batch(batch);
+                } else {
+                    for bomb in &batch {
+                        after.remove(&bomb.value);
+                    }
+                    set.remove_sorted_batch(&batch);
+                }
+            }));
+            FUSE.set(None);
+
+            // Either the whole batch went in or the set was left alone
+            let contents = values(&set).into_iter().collect::<BTreeSet<_>>();
+            (contents == after || (result.is_err() && contents == before))
+                && set.len() == contents.len()
+                && set.validate().is_ok()
+        });
+
+        drop(set);
+        consistent && dropped_once(ids)
+    }
+
+    #[quickcheck]
+    fn panicking_ord_keeps_batch_atomic(xs: Vec<u8>, batches: Vec<(bool, Vec<u8>, u8)>) -> bool {
+        batch_atomic::<AvlPolicy>(xs, batches)
+    }
+
+    #[quickcheck]
+    fn weight_balanced_panicking_ord_keeps_batch_atomic(xs: Vec<u8>, batches: Vec<(bool, Vec<u8>, u8)>) -> bool {
+        batch_atomic::<WeightBalancedPolicy>(xs, batches)
+    }
+
+    #[quickcheck]
+    fn treap_panicking_ord_keeps_batch_atomic(xs: Vec<u8>, batches: Vec<(bool, Vec<u8>, u8)>) -> bool {
+        batch_atomic::<TreapPolicy>(xs, batches)
+    }
+
+    #[test]
+    fn panicking_drop_in_batch() {
+        DROPS.take();
+        let mut ids = 0;
+        let mut set = AVLTreeSet::new();
+        set.insert_sorted_batch((0..32).map(|value| Bomb::new(value, &mut ids)));
+
+        // The rejected duplicate panics as it is dropped, once the batch is in
+        DROP_FUSE.set(Some(7));
+        let batch = [7, 40, 41].map(|value| Bomb::new(value, &mut ids));
+        assert!(catch_unwind(AssertUnwindSafe(|| set.insert_sorted_batch(batch))).is_err());
+        assert_eq!(set.len(), 34);
+        assert!(set.validate().is_ok());
+
+        // So does a removed element
+        DROP_FUSE.set(Some(20));
+        let batch = [10, 20, 30].map(|value| Bomb::new(value, &mut ids));
+        assert!(catch_unwind(AssertUnwindSafe(|| set.remove_sorted_batch(&batch))).is_err());
+        assert_eq!(values(&set), (0..32).chain(40..42).filter(|value| ![10, 20, 30].contains(value)).collect::<Vec<_>>());
+        assert!(set.validate().is_ok());
+
+        drop(batch);
+        drop(set);
+        assert!(dropped_once(ids));
+    }
+
+    #[test]
+    fn borrowed_batch() {
+        let mut set = ["a", "b", "c", "d"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect::<AVLTreeSet<String>>();
+
+        assert_eq!(set.remove_sorted_batch(["b", "c", "x"]), 2);
+        assert_eq!(set.iter().collect::<Vec<_>>(), ["a", "d"]);
+    }
+
+    #[test]
+    fn large_runs() {
+        let mut set = (0..10_000u32).step_by(2).collect::<AVLTreeSet<_>>();
+
+        assert_eq!(set.insert_sorted_batch(0..10_000), 5_000);
+        assert_eq!(set.len(), 10_000);
+        assert_eq!(set.remove_sorted_batch(&(5_000..20_000).collect::<Vec<_>>()), 5_000);
+        assert_eq!(set.len(), 5_000);
+        assert!(set.validate().is_ok());
+    }
+}
@@ -0,0 +1,291 @@
+/*
+    Bidirectional ordered map.
+    Every pair is kept in two AVLTreeMaps, one keyed by each side, so both
+    sides can be looked up and walked in order in O(log n). Each left value
+    is paired with at most one right value and the other way around, so a
+    pair conflicts with at most one existing pair on each side.
+*/
+
+use std::borrow::Borrow;
+use std::ops::RangeBounds;
+
+use crate::AVLTreeMap;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+/// The pairs an `AVLBiMap::insert` removed to make room for the new one.
+pub enum Overwritten<L, R> {
+    /// Neither value was in the map.
+    Neither,
+    /// The left value was paired with another right value, returned here.
+    Left(L, R),
+    /// The right value was paired with another left value, returned here.
+    Right(L, R),
+    /// The map already held the same pair.
+    Pair(L, R),
+    /// Both values were paired with others, the left value's pair coming first.
+    Both((L, R), (L, R)),
+}
+
+#[derive(Debug, Clone)]
+/// A one to one ordered map, looked up and iterated in order from either side.
+pub struct AVLBiMap<L: Ord + Clone, R: Ord + Clone> {
+    by_left: AVLTreeMap<L, R>,
+    by_right: AVLTreeMap<R, L>,
+}
+
+impl<L: Ord + Clone, R: Ord + Clone> Default for AVLBiMap<L, R> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<L: Ord + Clone, R: Ord + Clone> AVLBiMap<L, R> {
+    /// Creates a new, empty AVLBiMap.
+    pub fn new() -> Self {
+        Self {
+            by_left: AVLTreeMap::new(),
+            by_right: AVLTreeMap::new(),
+        }
+    }
+
+    /// Returns the number of pairs in the map.
+    pub fn len(&self) -> usize {
+        self.by_left.len()
+    }
+
+    /// Returns true if the map contains no pairs.
+    pub fn is_empty(&self) -> bool {
+        self.by_left.is_empty()
+    }
+
+    /// Pairs left with right, removing any pair either of them was already in.
+    pub fn insert(&mut self, left: L, right: R) -> Overwritten<L, R> {
+        let overwritten = match (self.remove_by_left(&left), self.remove_by_right(&right)) {
+            (None, None) => Overwritten::Neither,
+            (Some((l, r)), None) if r == right => Overwritten::Pair(l, r),
+            (Some((l, r)), None) => Overwritten::Left(l, r),
+            (None, Some((l, r))) => Overwritten::Right(l, r),
+            (Some(by_left), Some(by_right)) => Overwritten::Both(by_left, by_right),
+        };
+
+        self.by_left.insert(left.clone(), right.clone());
+        self.by_right.insert(right, left);
+
+        overwritten
+    }
+
+    /// Pairs left with right if neither is in the map yet. Otherwise returns them back unchanged.
+    pub fn try_insert(&mut self, left: L, right: R) -> Result<(), (L, R)> {
+        if self.contains_left(&left) || self.contains_right(&right) {
+            return Err((left, right));
+        }
+
+        self.by_left.insert(left.clone(), right.clone());
+        self.by_right.insert(right, left);
+        Ok(())
+    }
+
+    /// Returns the right value paired with left, if any.
+    pub fn get_by_left<Q>(&self, left: &Q) -> Option<&R>
+    where
+        L: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.by_left.get(left)
+    }
+
+    /// Returns the left value paired with right, if any.
+    pub fn get_by_right<Q>(&self, right: &Q) -> Option<&L>
+    where
+        R: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.by_right.get(right)
+    }
+
+    /// Returns true if left is in the map.
+    pub fn contains_left<Q>(&self, left: &Q) -> bool
+    where
+        L: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.by_left.contains_key(left)
+    }
+
+    /// Returns true if right is in the map.
+    pub fn contains_right<Q>(&self, right: &Q) -> bool
+    where
+        R: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.by_right.contains_key(right)
+    }
+
+    /// Removes and returns the pair holding left, if any.
+    pub fn remove_by_left<Q>(&mut self, left: &Q) -> Option<(L, R)>
+    where
+        L: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let (left, right) = self.by_left.remove_entry(left)?;
+        self.by_right.remove(&right);
+
+        Some((left, right))
+    }
+
+    /// Removes and returns the pair holding right, if any.
+    pub fn remove_by_right<Q>(&mut self, right: &Q) -> Option<(L, R)>
+    where
+        R: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let (right, left) = self.by_right.remove_entry(right)?;
+        self.by_left.remove(&left);
+
+        Some((left, right))
+    }
+
+    /// Gets an iterator that visits the pairs in ascending order of their left values.
+    pub fn iter_by_left(&self) -> impl Iterator<Item = (&'_ L, &'_ R)> + '_ {
+        self.by_left.iter()
+    }
+
+    /// Gets an iterator that visits the pairs in ascending order of their right values.
+    pub fn iter_by_right(&self) -> impl Iterator<Item = (&'_ L, &'_ R)> + '_ {
+        self.by_right.iter().map(|(right, left)| (left, right))
+    }
+
+    /// Gets a double ended iterator over the pairs with left values in range, in ascending left order.
+    pub fn range_by_left<Q, B>(&self, range: B) -> impl DoubleEndedIterator<Item = (&'_ L, &'_ R)> + '_
+    where
+        L: Borrow<Q>,
+        Q: Ord + ?Sized,
+        B: RangeBounds<Q>,
+    {
+        self.by_left.range(range)
+    }
+
+    /// Gets a double ended iterator over the pairs with right values in range, in ascending right order.
+    pub fn range_by_right<Q, B>(&self, range: B) -> impl DoubleEndedIterator<Item = (&'_ L, &'_ R)> + '_
+    where
+        R: Borrow<Q>,
+        Q: Ord + ?Sized,
+        B: RangeBounds<Q>,
+    {
+        self.by_right.range(range).map(|(right, left)| (left, right))
+    }
+}
+
+impl<L: Ord + Clone, R: Ord + Clone> FromIterator<(L, R)> for AVLBiMap<L, R> {
+    /// Create an AVLBiMap by inserting every pair in turn, later pairs overwriting earlier ones.
+    fn from_iter<I: IntoIterator<Item = (L, R)>>(iter: I) -> Self {
+        let mut map = Self::new();
+
+        for (left, right) in iter {
+            map.insert(left, right);
+        }
+
+        map
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::ops::Bound;
+
+    /// A pair of BTreeMaps kept in sync the slow way.
+    #[derive(Default)]
+    struct Model {
+        by_left: BTreeMap<u8, u8>,
+        by_right: BTreeMap<u8, u8>,
+    }
+
+    impl Model {
+        fn remove_by_left(&mut self, left: u8) -> Option<(u8, u8)> {
+            let right = self.by_left.remove(&left)?;
+            self.by_right.remove(&right);
+            Some((left, right))
+        }
+
+        fn remove_by_right(&mut self, right: u8) -> Option<(u8, u8)> {
+            let left = self.by_right.remove(&right)?;
+            self.by_left.remove(&left);
+            Some((left, right))
+        }
+
+        fn insert(&mut self, left: u8, right: u8) -> Overwritten<u8, u8> {
+            let existing = (self.by_left.get(&left).copied(), self.by_right.get(&right).copied());
+            let by_left = self.remove_by_left(left);
+            let by_right = self.remove_by_right(right);
+            self.by_left.insert(left, right);
+            self.by_right.insert(right, left);
+
+            match (existing, by_left, by_right) {
+                ((Some(r), _), _, _) if r == right => Overwritten::Pair(left, right),
+                (_, None, None) => Overwritten::Neither,
+                (_, Some(pair), None) => Overwritten::Left(pair.0, pair.1),
+                (_, None, Some(pair)) => Overwritten::Right(pair.0, pair.1),
+                (_, Some(by_left), Some(by_right)) => Overwritten::Both(by_left, by_right),
+            }
+        }
+    }
+
+    #[quickcheck]
+    fn bimap_parity(ops: Vec<(u8, u8, u8)>, a: u8, b: u8) -> bool {
+        let mut map = AVLBiMap::new();
+        let mut model = Model::default();
+        let (a, b) = (a % 16, b % 16);
+        let (low, high) = (a.min(b), a.max(b));
+
+        ops.into_iter().all(|(op, left, right)| {
+            let (left, right) = (left % 16, right % 16);
+            let same = match op % 4 {
+                0 => map.insert(left, right) == model.insert(left, right),
+                1 => {
+                    let free = !model.by_left.contains_key(&left) && !model.by_right.contains_key(&right);
+                    if free {
+                        model.insert(left, right);
+                    }
+                    map.try_insert(left, right) == if free { Ok(()) } else { Err((left, right)) }
+                }
+                2 => map.remove_by_left(&left) == model.remove_by_left(left),
+                _ => map.remove_by_right(&right) == model.remove_by_right(right),
+            };
+
+            same && map.len() == model.by_left.len()
+                && map.get_by_left(&left) == model.by_left.get(&left)
+                && map.get_by_right(&right) == model.by_right.get(&right)
+                && map.iter_by_left().eq(model.by_left.iter())
+                && map.iter_by_right().eq(model.by_right.iter().map(|(r, l)| (l, r)))
+                && map.range_by_left(low..=high).eq(model.by_left.range(low..=high))
+                && map
+                    .range_by_right(low..high)
+                    .eq(model.by_right.range(low..high).map(|(r, l)| (l, r)))
+        })
+    }
+
+    #[test]
+    fn ids_and_names() {
+        let mut users = [(1, "ada"), (2, "grace"), (3, "linus")]
+            .into_iter()
+            .map(|(id, name)| (id, name.to_string()))
+            .collect::<AVLBiMap<u32, String>>();
+
+        assert_eq!(users.get_by_right("grace"), Some(&2));
+        assert_eq!(users.get_by_left(&3).map(String::as_str), Some("linus"));
+        assert_eq!(users.try_insert(4, "ada".to_string()), Err((4, "ada".to_string())));
+
+        // Renaming user 3 to a taken name drops the other user holding it
+        assert_eq!(
+            users.insert(3, "ada".to_string()),
+            Overwritten::Both((3, "linus".to_string()), (1, "ada".to_string()))
+        );
+        assert_eq!(users.insert(3, "ada".to_string()), Overwritten::Pair(3, "ada".to_string()));
+        let a_to_h = (Bound::Included("a"), Bound::Excluded("h"));
+        assert_eq!(users.range_by_right::<str, _>(a_to_h).map(|(id, _)| *id).collect::<Vec<_>>(), [3, 2]);
+        assert_eq!(users.remove_by_right("grace"), Some((2, "grace".to_string())));
+        assert_eq!(users.len(), 1);
+    }
+}
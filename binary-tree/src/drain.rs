@@ -0,0 +1,191 @@
+/*
+    Removing a whole range of elements at once.
+    With AvlPolicy the range is cut out with two splits by position and the
+    remaining halves are joined, so only O(log n) nodes are rebalanced however
+    many elements the range holds. Other policies take the elements one by one.
+*/
+
+use std::borrow::Borrow;
+use std::ops::RangeBounds;
+
+use crate::ops::{build, join2, split_at};
+use crate::{range_predicates, AVLNode, AVLTree, AVLTreeSet, BalancePolicy};
+
+impl<T: Ord, P: BalancePolicy> AVLTreeSet<T, P> {
+    /// Removes every element in range, returning how many were removed.
+    ///
+    /// Takes O(log n + k) for k removed elements with `AvlPolicy`, and O(k log n) otherwise.
+    pub fn remove_range<Q, R>(&mut self, range: R) -> usize
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+        R: RangeBounds<Q>,
+    {
+        self.drain_range(range).len()
+    }
+
+    /// Removes every element in range, returning them in ascending order.
+    ///
+    /// The elements are removed from the set right away, even if the iterator is not used up.
+    pub fn drain_range<Q, R>(&mut self, range: R) -> AVLTreeSetDrain<T>
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+        R: RangeBounds<Q>,
+    {
+        let (before_start, before_end) = range_predicates(&range);
+        let start = self.rank(before_start);
+        let end = self.rank(before_end).max(start);
+
+        let drained = if let Some(root) = P::as_avl(&mut self.root) {
+            let (left, rest) = split_at(root.take(), start);
+            let (drained, right) = split_at(rest, end - start);
+            *root = join2(left, right);
+            self.len -= end - start;
+            drained
+        } else {
+            build((start..end).map(|_| self.take_at(start).unwrap()).collect())
+        };
+
+        #[cfg(feature = "debug-invariants")]
+        self.assert_invariants();
+
+        AVLTreeSetDrain::new(drained, end - start)
+    }
+}
+
+#[derive(Debug)]
+/// An iterator over the elements removed by `AVLTreeSet::drain_range`.
+pub struct AVLTreeSetDrain<T> {
+    /// Nodes still to visit, each with its left side already visited
+    prev_nodes: Vec<Box<AVLNode<T>>>,
+    remaining: usize,
+}
+
+impl<T> AVLTreeSetDrain<T> {
+    fn new(tree: AVLTree<T>, len: usize) -> Self {
+        let mut drain = Self {
+            prev_nodes: Vec::new(),
+            remaining: len,
+        };
+        drain.push_left_spine(tree);
+        drain
+    }
+
+    fn push_left_spine(&mut self, mut current_tree: AVLTree<T>) {
+        while let Some(mut current_node) = current_tree {
+            current_tree = current_node.left.take();
+            self.prev_nodes.push(current_node);
+        }
+    }
+}
+
+impl<T> Iterator for AVLTreeSetDrain<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut node = self.prev_nodes.pop()?;
+        self.remaining -= 1;
+        self.push_left_spine(node.right.take());
+
+        Some(node.value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<T> ExactSizeIterator for AVLTreeSetDrain<T> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AvlPolicy, TreapPolicy, WeightBalancedPolicy};
+    use std::collections::BTreeSet;
+    use std::ops::Bound;
+
+    fn bounds(low: u16, high: u16, kind: u8) -> (Bound<u16>, Bound<u16>) {
+        let (low, high) = (low.min(high), low.max(high));
+        match kind % 5 {
+            0 => (Bound::Included(low), Bound::Included(high)),
+            1 => (Bound::Included(low), Bound::Excluded(high)),
+            2 => (Bound::Excluded(low), Bound::Included(high)),
+            3 => (Bound::Unbounded, Bound::Excluded(high)),
+            _ => (Bound::Included(low), Bound::Unbounded),
+        }
+    }
+
+    fn drain_range<P: BalancePolicy + Default>(xs: Vec<u16>, low: u16, high: u16, kind: u8) -> bool {
+        let mut set = xs.iter().cloned().collect::<AVLTreeSet<_, P>>();
+        let mut btree_set = xs.into_iter().collect::<BTreeSet<_>>();
+        let range = bounds(low, high, kind);
+
+        let expected = btree_set.range(range).cloned().collect::<Vec<_>>();
+        btree_set.retain(|x| !range.contains(x));
+
+        set.drain_range(range).eq(expected)
+            && set.len() == btree_set.len()
+            && set.validate().is_ok()
+            && set.iter().eq(btree_set.iter())
+    }
+
+    #[quickcheck]
+    fn drain_range_parity(xs: Vec<u16>, low: u16, high: u16, kind: u8) -> bool {
+        drain_range::<AvlPolicy>(xs, low, high, kind)
+    }
+
+    #[quickcheck]
+    fn weight_balanced_drain_range_parity(xs: Vec<u16>, low: u16, high: u16, kind: u8) -> bool {
+        drain_range::<WeightBalancedPolicy>(xs, low, high, kind)
+    }
+
+    #[quickcheck]
+    fn treap_drain_range_parity(xs: Vec<u16>, low: u16, high: u16, kind: u8) -> bool {
+        drain_range::<TreapPolicy>(xs, low, high, kind)
+    }
+
+    fn remove_range<P: BalancePolicy + Default>(xs: Vec<u16>, low: u16, high: u16, kind: u8) -> bool {
+        let mut set = xs.iter().cloned().collect::<AVLTreeSet<_, P>>();
+        let mut btree_set = xs.into_iter().collect::<BTreeSet<_>>();
+        let range = bounds(low, high, kind);
+
+        let before = btree_set.len();
+        btree_set.retain(|x| !range.contains(x));
+
+        set.remove_range(range) == before - btree_set.len()
+            && set.validate().is_ok()
+            && set.iter().eq(btree_set.iter())
+    }
+
+    #[quickcheck]
+    fn remove_range_parity(xs: Vec<u16>, low: u16, high: u16, kind: u8) -> bool {
+        remove_range::<AvlPolicy>(xs, low, high, kind)
+    }
+
+    #[quickcheck]
+    fn weight_balanced_remove_range_parity(xs: Vec<u16>, low: u16, high: u16, kind: u8) -> bool {
+        remove_range::<WeightBalancedPolicy>(xs, low, high, kind)
+    }
+
+    #[quickcheck]
+    fn treap_remove_range_parity(xs: Vec<u16>, low: u16, high: u16, kind: u8) -> bool {
+        remove_range::<TreapPolicy>(xs, low, high, kind)
+    }
+
+    #[test]
+    fn expire_below_cutoff() {
+        let mut set = (0..1000u32).collect::<AVLTreeSet<_>>();
+
+        let mut drain = set.drain_range(..300);
+        assert_eq!(drain.len(), 300);
+        assert_eq!(drain.next(), Some(0));
+        drop(drain);
+
+        assert_eq!(set.len(), 700);
+        assert_eq!(set.first(), Some(&300));
+        assert_eq!(set.remove_range(900..), 100);
+        assert_eq!(set.remove_range(5000..), 0);
+        assert_eq!(set.iter().cloned().collect::<Vec<_>>(), (300..900).collect::<Vec<_>>());
+    }
+}
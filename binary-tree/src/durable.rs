@@ -0,0 +1,470 @@
+/*
+    Durable AVLTreeSet backed by a write-ahead log.
+
+    A directory holds two files:
+        snapshot    the set in the snapshot format, see snapshot.rs
+        wal         records appended since the snapshot was written
+
+    Each log record is, little endian:
+        op          u8       1 for insert, 2 for take
+        length      u32
+        element     length bytes, encoded with ElementCodec
+        checksum    u64      FNV-1a of op, length and element
+
+    Every change is appended to the log and synced before it is applied in memory.
+    On open the snapshot is loaded and the log replayed up to the first incomplete
+    or damaged record, which is where a crash during a write leaves the log.
+    Replaying is idempotent, so a crash during compaction is also safe.
+*/
+
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::snapshot::Checksum;
+use crate::{AVLTreeSet, ElementCodec, SnapshotError};
+
+const SNAPSHOT_FILE: &str = "snapshot";
+const SNAPSHOT_TEMP_FILE: &str = "snapshot.tmp";
+const LOG_FILE: &str = "wal";
+
+const INSERT: u8 = 1;
+const TAKE: u8 = 2;
+
+/// Size of a record without its element.
+const RECORD_OVERHEAD: usize = 1 + 4 + 8;
+
+/// Number of log records after which the log is compacted into a new snapshot.
+const DEFAULT_COMPACT_AFTER: usize = 1024;
+
+#[derive(Debug)]
+/// Reasons a DurableSet could not be opened or updated.
+pub enum DurableError {
+    /// Reading or writing a file failed.
+    Io(io::Error),
+    /// The snapshot could not be loaded or saved.
+    Snapshot(SnapshotError),
+    /// A log record with a valid checksum holds an unknown operation or an undecodable element.
+    CorruptLog { offset: u64 },
+    /// An element encodes to more bytes than a log record can hold.
+    ElementTooLarge,
+}
+
+impl fmt::Display for DurableError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DurableError::Io(err) => write!(f, "durable set io error: {}", err),
+            DurableError::Snapshot(err) => write!(f, "durable set snapshot error: {}", err),
+            DurableError::CorruptLog { offset } => {
+                write!(f, "corrupt log record at offset {}", offset)
+            }
+            DurableError::ElementTooLarge => write!(f, "element is too large for a log record"),
+        }
+    }
+}
+
+impl Error for DurableError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            DurableError::Io(err) => Some(err),
+            DurableError::Snapshot(err) => Some(err),
+            DurableError::CorruptLog { .. } | DurableError::ElementTooLarge => None,
+        }
+    }
+}
+
+impl From<io::Error> for DurableError {
+    fn from(err: io::Error) -> Self {
+        DurableError::Io(err)
+    }
+}
+
+impl From<SnapshotError> for DurableError {
+    fn from(err: SnapshotError) -> Self {
+        DurableError::Snapshot(err)
+    }
+}
+
+#[derive(Debug)]
+/// An AVLTreeSet whose changes survive a crash.
+pub struct DurableSet<T: Ord + ElementCodec> {
+    set: AVLTreeSet<T>,
+    dir: PathBuf,
+    log: File,
+    /// Length of the log up to the end of the last complete record
+    log_len: u64,
+    log_records: usize,
+    compact_after: usize,
+}
+
+impl<T: Ord + ElementCodec> DurableSet<T> {
+    /// Opens the set stored in dir, creating an empty one if there is none.
+    ///
+    /// Recovers from a crash by dropping any incomplete record at the end of the log.
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self, DurableError> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+
+        let mut set = match File::open(dir.join(SNAPSHOT_FILE)) {
+            Ok(file) => AVLTreeSet::load_from(BufReader::new(file))?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => AVLTreeSet::new(),
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut log = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(dir.join(LOG_FILE))?;
+
+        let mut bytes = Vec::new();
+        log.read_to_end(&mut bytes)?;
+        let (log_len, log_records) = replay(&bytes, &mut set)?;
+
+        // Cut off the damaged tail so new records follow the last complete one
+        if log_len < bytes.len() as u64 {
+            log.set_len(log_len)?;
+            log.sync_data()?;
+        }
+        log.seek(SeekFrom::Start(log_len))?;
+
+        Ok(Self {
+            set,
+            dir,
+            log,
+            log_len,
+            log_records,
+            compact_after: DEFAULT_COMPACT_AFTER,
+        })
+    }
+
+    /// Sets the number of log records after which the log is compacted into a snapshot.
+    pub fn set_compact_after(&mut self, records: usize) {
+        self.compact_after = records.max(1);
+    }
+
+    /// Adds a value to the set, logging it first.
+    ///
+    /// Returns true if the set did not have an equal element present.
+    /// Errors only if the change could not be logged, in which case the set is unchanged.
+    pub fn insert(&mut self, value: T) -> Result<bool, DurableError> {
+        if self.set.contains(&value) {
+            return Ok(false);
+        }
+
+        self.append(INSERT, &value)?;
+        self.set.insert(value);
+        self.compact_if_due();
+
+        Ok(true)
+    }
+
+    /// Removes and returns the element equal to the value, logging it first.
+    ///
+    /// Errors only if the change could not be logged, in which case the set is unchanged.
+    pub fn take(&mut self, value: &T) -> Result<Option<T>, DurableError> {
+        if !self.set.contains(value) {
+            return Ok(None);
+        }
+
+        self.append(TAKE, value)?;
+        let taken = self.set.take(value);
+        self.compact_if_due();
+
+        Ok(taken)
+    }
+
+    /// Returns true if the set contains an element equal to the value.
+    pub fn contains(&self, value: &T) -> bool {
+        self.set.contains(value)
+    }
+
+    /// Returns the number of elements in the set.
+    pub fn len(&self) -> usize {
+        self.set.len()
+    }
+
+    /// Returns true if the set contains no elements.
+    pub fn is_empty(&self) -> bool {
+        self.set.is_empty()
+    }
+
+    /// Gets an iterator that visits the elements in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = &'_ T> + '_ {
+        self.set.iter()
+    }
+
+    /// Returns the in memory set.
+    pub fn as_set(&self) -> &AVLTreeSet<T> {
+        &self.set
+    }
+
+    /// Writes the whole set to a new snapshot and empties the log.
+    pub fn compact(&mut self) -> Result<(), DurableError> {
+        let temp_path = self.dir.join(SNAPSHOT_TEMP_FILE);
+        let mut writer = BufWriter::new(File::create(&temp_path)?);
+        self.set.save_to(&mut writer)?;
+        writer.into_inner().map_err(|err| err.into_error())?.sync_all()?;
+
+        // Replace the old snapshot in one step, then drop the records it now holds
+        fs::rename(&temp_path, self.dir.join(SNAPSHOT_FILE))?;
+        #[cfg(unix)]
+        File::open(&self.dir)?.sync_all()?;
+
+        self.log.set_len(0)?;
+        self.log.seek(SeekFrom::Start(0))?;
+        self.log.sync_data()?;
+        self.log_len = 0;
+        self.log_records = 0;
+
+        Ok(())
+    }
+
+    /// Compacts if the log has grown long enough.
+    ///
+    /// The change that grew the log is already durable in it, so a failed compaction
+    /// only leaves the log long, and is tried again after the next change.
+    fn compact_if_due(&mut self) {
+        if self.log_records >= self.compact_after {
+            let _ = self.compact();
+        }
+    }
+
+    /// Appends a record to the log and syncs it.
+    fn append(&mut self, op: u8, value: &T) -> Result<(), DurableError> {
+        let record = encode_record(op, value)?;
+
+        let written = self
+            .log
+            .write_all(&record)
+            .and_then(|_| self.log.sync_data());
+
+        if let Err(err) = written {
+            // Do not leave a partial record for later records to follow
+            let _ = self.log.set_len(self.log_len);
+            let _ = self.log.seek(SeekFrom::Start(self.log_len));
+            return Err(err.into());
+        }
+
+        self.log_len += record.len() as u64;
+        self.log_records += 1;
+
+        Ok(())
+    }
+}
+
+fn encode_record<T: ElementCodec>(op: u8, value: &T) -> Result<Vec<u8>, DurableError> {
+    let mut element = Vec::new();
+    value.encode(&mut element);
+    let len = u32::try_from(element.len()).map_err(|_| DurableError::ElementTooLarge)?;
+
+    let mut record = Vec::with_capacity(RECORD_OVERHEAD + element.len());
+    record.push(op);
+    record.extend_from_slice(&len.to_le_bytes());
+    record.extend_from_slice(&element);
+
+    let mut checksum = Checksum::new();
+    checksum.update(&record);
+    record.extend_from_slice(&checksum.value().to_le_bytes());
+
+    Ok(record)
+}
+
+/// Applies every complete record in the log to set.
+/// Returns the length of the log up to the end of the last complete record and the number of records.
+fn replay<T: Ord + ElementCodec>(log: &[u8], set: &mut AVLTreeSet<T>) -> Result<(u64, usize), DurableError> {
+    let mut offset = 0;
+    let mut records = 0;
+
+    while log.len() - offset >= RECORD_OVERHEAD {
+        let op = log[offset];
+        let len = u32::from_le_bytes(log[offset + 1..offset + 5].try_into().unwrap()) as usize;
+        let element_start = offset + 5;
+
+        // Incomplete record, the write was cut short
+        if log.len() - element_start < len + 8 {
+            break;
+        }
+
+        let element_end = element_start + len;
+        let mut checksum = Checksum::new();
+        checksum.update(&log[offset..element_end]);
+        let stored = u64::from_le_bytes(log[element_end..element_end + 8].try_into().unwrap());
+
+        // Damaged record, the write was torn
+        if stored != checksum.value() {
+            break;
+        }
+
+        let corrupt = || DurableError::CorruptLog { offset: offset as u64 };
+        let value = T::decode(&log[element_start..element_end]).ok_or_else(corrupt)?;
+        match op {
+            INSERT => {
+                set.insert(value);
+            }
+            TAKE => {
+                set.take(&value);
+            }
+            _ => return Err(corrupt()),
+        }
+
+        offset = element_end + 8;
+        records += 1;
+    }
+
+    Ok((offset as u64, records))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// A fresh directory that is removed when dropped
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new() -> Self {
+            static COUNTER: AtomicUsize = AtomicUsize::new(0);
+            let path = std::env::temp_dir().join(format!(
+                "binary-tree-durable-{}-{}",
+                std::process::id(),
+                COUNTER.fetch_add(1, Ordering::Relaxed)
+            ));
+            let _ = fs::remove_dir_all(&path);
+            Self(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// Applies operations, a positive number inserts it and a negative takes its absolute value.
+    /// Values are kept below 1000.
+    /// Returns the contents after every logged change, starting with the initial contents.
+    fn apply(set: &mut DurableSet<u16>, ops: &[i32]) -> Vec<Vec<u16>> {
+        let mut states = vec![set.iter().cloned().collect()];
+
+        for &op in ops {
+            let value = (op.unsigned_abs() % 1000) as u16;
+            let changed = if op >= 0 {
+                set.insert(value).unwrap()
+            } else {
+                set.take(&value).unwrap().is_some()
+            };
+
+            if changed {
+                states.push(set.iter().cloned().collect());
+            }
+        }
+
+        states
+    }
+
+    #[quickcheck]
+    fn reopen_restores_contents(ops: Vec<i32>, compact_after: u8) -> bool {
+        let dir = TempDir::new();
+        let mut set = DurableSet::open(&dir.0).unwrap();
+        set.set_compact_after(compact_after as usize);
+        let states = apply(&mut set, &ops);
+        drop(set);
+
+        let reopened = DurableSet::<u16>::open(&dir.0).unwrap();
+
+        reopened.as_set().validate().is_ok() && reopened.iter().eq(states.last().unwrap().iter())
+    }
+
+    #[quickcheck]
+    fn truncated_log_recovers_prefix(ops: Vec<i32>, cut: usize) -> bool {
+        let dir = TempDir::new();
+        let mut set = DurableSet::open(&dir.0).unwrap();
+        set.set_compact_after(usize::MAX);
+        let states = apply(&mut set, &ops);
+        drop(set);
+
+        // Simulate a crash that lost the end of the log
+        let log_path = dir.0.join(LOG_FILE);
+        let len = fs::metadata(&log_path).unwrap().len();
+        let cut = cut as u64 % (len + 1);
+        OpenOptions::new().write(true).open(&log_path).unwrap().set_len(cut).unwrap();
+
+        let mut recovered = DurableSet::<u16>::open(&dir.0).unwrap();
+        let contents = recovered.iter().cloned().collect::<Vec<_>>();
+        let records = recovered.log_records;
+
+        // Recovery keeps exactly the complete records, and new records follow them
+        let prefix_consistent = contents == states[records];
+        recovered.insert(1000).unwrap();
+        drop(recovered);
+        let reopened = DurableSet::<u16>::open(&dir.0).unwrap();
+
+        prefix_consistent && reopened.contains(&1000) && reopened.len() == contents.len() + 1
+    }
+
+    #[test]
+    fn compacts_into_snapshot() {
+        let dir = TempDir::new();
+        let mut set = DurableSet::open(&dir.0).unwrap();
+        set.set_compact_after(10);
+
+        for i in 0..25u16 {
+            set.insert(i).unwrap();
+        }
+
+        assert_eq!(set.log_records, 5);
+        assert!(dir.0.join(SNAPSHOT_FILE).exists());
+        drop(set);
+
+        let reopened = DurableSet::<u16>::open(&dir.0).unwrap();
+        assert!(reopened.iter().cloned().eq(0..25));
+    }
+
+    #[test]
+    fn failed_compaction_keeps_change() {
+        let dir = TempDir::new();
+        let mut set = DurableSet::open(&dir.0).unwrap();
+        set.set_compact_after(1);
+
+        // A directory in the way of the temporary snapshot makes compaction fail
+        fs::create_dir(dir.0.join(SNAPSHOT_TEMP_FILE)).unwrap();
+        assert!(set.insert(1u32).unwrap());
+        assert!(set.insert(2u32).unwrap());
+        assert_eq!(set.take(&1).unwrap(), Some(1));
+        assert_eq!(set.log_records, 3);
+
+        // The next change compacts once the way is clear
+        fs::remove_dir(dir.0.join(SNAPSHOT_TEMP_FILE)).unwrap();
+        set.insert(3).unwrap();
+        assert_eq!(set.log_records, 0);
+        drop(set);
+
+        let reopened = DurableSet::<u32>::open(&dir.0).unwrap();
+        assert!(reopened.iter().cloned().eq([2, 3]));
+    }
+
+    #[test]
+    fn damaged_record_is_dropped() {
+        let dir = TempDir::new();
+        let mut set = DurableSet::open(&dir.0).unwrap();
+        set.insert(1u32).unwrap();
+        set.insert(2u32).unwrap();
+        drop(set);
+
+        // Flip a byte in the last record's element
+        let log_path = dir.0.join(LOG_FILE);
+        let mut bytes = fs::read(&log_path).unwrap();
+        let len = bytes.len();
+        bytes[len - 9] ^= 0xff;
+        fs::write(&log_path, bytes).unwrap();
+
+        let reopened = DurableSet::<u32>::open(&dir.0).unwrap();
+        assert!(reopened.iter().cloned().eq([1]));
+        assert_eq!(fs::metadata(&log_path).unwrap().len(), (len / 2) as u64);
+    }
+}
@@ -0,0 +1,369 @@
+/*
+    Set whose elements expire after a time to live.
+    Each element's deadline is kept in an AVLTreeMap, and an AVLTreeSet
+    ordered by (deadline, element) serves as the secondary index that
+    purging and the next deadline query walk from the front.
+*/
+
+use std::borrow::Borrow;
+use std::cell::Cell;
+use std::mem::replace;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use crate::{AVLTreeMap, AVLTreeSet};
+
+/// Source of the current time for an ExpiringSet.
+pub trait Clock {
+    fn now(&self) -> Instant;
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+/// The system's monotonic clock.
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+#[derive(Debug, Clone)]
+/// A clock that only moves when told to. Clones share the same time.
+pub struct ManualClock {
+    now: Rc<Cell<Instant>>,
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ManualClock {
+    /// Creates a clock stopped at the current time.
+    pub fn new() -> Self {
+        Self {
+            now: Rc::new(Cell::new(Instant::now())),
+        }
+    }
+
+    /// Moves the clock forward.
+    pub fn advance(&self, duration: Duration) {
+        self.now.set(self.now.get() + duration);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.now.get()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+/// When an element expires. Never comes after every instant.
+enum Deadline {
+    At(Instant),
+    Never,
+}
+
+impl Deadline {
+    /// The deadline ttl after now, or Never if that is past the latest representable instant.
+    fn after(now: Instant, ttl: Duration) -> Self {
+        now.checked_add(ttl).map_or(Deadline::Never, Deadline::At)
+    }
+
+    fn has_passed(self, now: Instant) -> bool {
+        self <= Deadline::At(now)
+    }
+
+    fn instant(self) -> Option<Instant> {
+        match self {
+            Deadline::At(instant) => Some(instant),
+            Deadline::Never => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+/// A deadline and the time to live it is refreshed with.
+struct Expiry {
+    deadline: Deadline,
+    ttl: Duration,
+}
+
+#[derive(Debug, Clone)]
+/// A set whose elements disappear once their time to live has passed.
+///
+/// Expired elements are not visible, but only give up their memory when purged.
+pub struct ExpiringSet<T: Ord + Clone, C: Clock = SystemClock> {
+    expiries: AVLTreeMap<T, Expiry>,
+    by_deadline: AVLTreeSet<(Deadline, T)>,
+    clock: C,
+}
+
+impl<T: Ord + Clone> Default for ExpiringSet<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Ord + Clone> ExpiringSet<T> {
+    /// Creates a new, empty ExpiringSet using the system clock.
+    pub fn new() -> Self {
+        Self::with_clock(SystemClock)
+    }
+}
+
+impl<T: Ord + Clone, C: Clock> ExpiringSet<T, C> {
+    /// Creates a new, empty ExpiringSet using clock.
+    pub fn with_clock(clock: C) -> Self {
+        Self {
+            expiries: AVLTreeMap::new(),
+            by_deadline: AVLTreeSet::new(),
+            clock,
+        }
+    }
+
+    /// Returns the number of elements, including expired ones not yet purged.
+    pub fn len(&self) -> usize {
+        self.expiries.len()
+    }
+
+    /// Returns true if the set holds no elements, expired or not.
+    pub fn is_empty(&self) -> bool {
+        self.expiries.is_empty()
+    }
+
+    /// Adds value with a time to live, or refreshes it with the new time to live.
+    ///
+    /// A time to live too large to add to the current time, such as `Duration::MAX`, never expires.
+    ///
+    /// Returns true if the set did not have a live equal element present.
+    pub fn insert_with_ttl(&mut self, value: T, ttl: Duration) -> bool {
+        let now = self.clock.now();
+        let deadline = Deadline::after(now, ttl);
+
+        let was_live = match self.expiries.get_mut(&value) {
+            Some(expiry) => {
+                let old = replace(expiry, Expiry { deadline, ttl });
+                let (_, value) = self.by_deadline.take(&(old.deadline, value)).unwrap();
+                self.by_deadline.insert((deadline, value));
+                !old.deadline.has_passed(now)
+            }
+            None => {
+                self.by_deadline.insert((deadline, value.clone()));
+                self.expiries.insert(value, Expiry { deadline, ttl });
+                false
+            }
+        };
+
+        !was_live
+    }
+
+    /// Returns true if the set contains a live element equal to value.
+    pub fn contains<Q>(&self, value: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let now = self.clock.now();
+        self.expiries
+            .get(value)
+            .is_some_and(|expiry| !expiry.deadline.has_passed(now))
+    }
+
+    /// Returns the deadline of value if it is live and ever expires.
+    pub fn deadline<Q>(&self, value: &Q) -> Option<Instant>
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let now = self.clock.now();
+        self.expiries
+            .get(value)
+            .map(|expiry| expiry.deadline)
+            .filter(|deadline| !deadline.has_passed(now))
+            .and_then(Deadline::instant)
+    }
+
+    /// Restarts the time to live of a live element. Returns false if there is none.
+    pub fn touch<Q>(&mut self, value: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let now = self.clock.now();
+        let Some(expiry) = self.expiries.get_mut(value).filter(|expiry| !expiry.deadline.has_passed(now)) else {
+            return false;
+        };
+
+        let old_deadline = expiry.deadline;
+        expiry.deadline = Deadline::after(now, expiry.ttl);
+        let deadline = expiry.deadline;
+
+        let (_, value) = self
+            .by_deadline
+            .take_by(|(d, t)| d.cmp(&old_deadline).then_with(|| t.borrow().cmp(value)))
+            .unwrap();
+        self.by_deadline.insert((deadline, value));
+
+        true
+    }
+
+    /// Removes value, returning true if it was present and live.
+    pub fn remove<Q>(&mut self, value: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let Some(expiry) = self.expiries.remove(value) else {
+            return false;
+        };
+
+        self.by_deadline
+            .take_by(|(d, t)| d.cmp(&expiry.deadline).then_with(|| t.borrow().cmp(value)));
+
+        !expiry.deadline.has_passed(self.clock.now())
+    }
+
+    /// Removes every element whose deadline is at or before now, returning them by deadline.
+    ///
+    /// Takes O(k log n) for k purged elements.
+    pub fn purge_expired(&mut self, now: Instant) -> Vec<T> {
+        let mut purged = Vec::new();
+
+        while self.by_deadline.first().is_some_and(|(deadline, _)| deadline.has_passed(now)) {
+            let (_, value) = self.by_deadline.pop_first().unwrap();
+            self.expiries.remove(&value);
+            purged.push(value);
+        }
+
+        purged
+    }
+
+    /// Returns the earliest deadline of any element, expired or not, leaving out those that never expire.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.by_deadline.first().and_then(|(deadline, _)| deadline.instant())
+    }
+
+    /// Returns the clock the set reads the time from.
+    pub fn clock(&self) -> &C {
+        &self.clock
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn live(model: &BTreeMap<u8, (u64, u64)>, value: u8, now: u64) -> bool {
+        model.get(&value).is_some_and(|&(deadline, _)| deadline > now)
+    }
+
+    #[quickcheck]
+    fn expiry_parity(ops: Vec<(u8, u8, u8)>) -> bool {
+        let clock = ManualClock::new();
+        let start = clock.now();
+        let mut set = ExpiringSet::with_clock(clock.clone());
+        // Model from element to (deadline, ttl) in seconds since start
+        let mut model = BTreeMap::<u8, (u64, u64)>::new();
+        let mut now = 0;
+
+        ops.into_iter().all(|(op, value, amount)| {
+            let value = value % 16;
+            let amount = amount as u64 % 8;
+            let at = |seconds| start + Duration::from_secs(seconds);
+
+            let same = match op % 5 {
+                0 | 1 => {
+                    let expected = !live(&model, value, now);
+                    model.insert(value, (now + amount, amount));
+                    set.insert_with_ttl(value, Duration::from_secs(amount)) == expected
+                }
+                2 => {
+                    let expected = live(&model, value, now);
+                    if expected {
+                        let entry = model.get_mut(&value).unwrap();
+                        entry.0 = now + entry.1;
+                    }
+                    set.touch(&value) == expected
+                }
+                3 => {
+                    let expected = live(&model, value, now);
+                    model.remove(&value);
+                    set.remove(&value) == expected
+                }
+                _ => {
+                    clock.advance(Duration::from_secs(amount));
+                    now += amount;
+
+                    let mut expected = model
+                        .iter()
+                        .filter(|(_, &(deadline, _))| deadline <= now)
+                        .map(|(&value, &(deadline, _))| (deadline, value))
+                        .collect::<Vec<_>>();
+                    expected.sort();
+                    model.retain(|_, &mut (deadline, _)| deadline > now);
+
+                    set.purge_expired(at(now)) == expected.into_iter().map(|(_, value)| value).collect::<Vec<_>>()
+                }
+            };
+
+            same && set.len() == model.len()
+                && (0..16).all(|value| set.contains(&value) == live(&model, value, now))
+                && set.next_deadline() == model.values().map(|&(deadline, _)| at(deadline)).min()
+        })
+    }
+
+    #[test]
+    fn sessions_time_out() {
+        let clock = ManualClock::new();
+        let mut sessions = ExpiringSet::with_clock(clock.clone());
+        let minute = Duration::from_secs(60);
+
+        assert!(sessions.insert_with_ttl("alice".to_string(), minute));
+        assert!(sessions.insert_with_ttl("bob".to_string(), 2 * minute));
+        assert_eq!(sessions.next_deadline(), Some(clock.now() + minute));
+
+        clock.advance(minute / 2);
+        assert!(sessions.touch("alice"));
+        clock.advance(minute);
+
+        // Bob is still live, Alice's refreshed deadline has passed
+        assert!(sessions.contains("bob"));
+        assert!(!sessions.contains("alice"));
+        assert!(!sessions.touch("alice"));
+        assert_eq!(sessions.purge_expired(clock.now()), ["alice"]);
+        assert_eq!(sessions.len(), 1);
+
+        clock.advance(minute);
+        assert_eq!(sessions.purge_expired(clock.now()), ["bob"]);
+        assert!(sessions.is_empty());
+        assert_eq!(sessions.next_deadline(), None);
+    }
+
+    #[test]
+    fn max_ttl_never_expires() {
+        let clock = ManualClock::new();
+        let mut set = ExpiringSet::with_clock(clock.clone());
+        let minute = Duration::from_secs(60);
+
+        assert!(set.insert_with_ttl("forever", Duration::MAX));
+        assert!(set.insert_with_ttl("brief", minute));
+        assert!(set.touch("forever"));
+        assert_eq!(set.deadline("forever"), None);
+        assert_eq!(set.next_deadline(), Some(clock.now() + minute));
+
+        clock.advance(Duration::from_secs(1_000_000_000));
+        assert_eq!(set.purge_expired(clock.now()), ["brief"]);
+        assert!(set.contains("forever"));
+        assert_eq!(set.next_deadline(), None);
+
+        // Refreshing with a finite time to live makes it expire again
+        assert!(!set.insert_with_ttl("forever", minute));
+        assert_eq!(set.next_deadline(), Some(clock.now() + minute));
+        assert!(set.remove("forever"));
+    }
+}
@@ -0,0 +1,276 @@
+/*
+    Read-only set in Eytzinger layout.
+    The elements sit in one array in breadth first order of a complete
+    binary search tree: the children of the node at 1-based index k are at
+    2k and 2k + 1. A search only computes the next index, with no pointers
+    to follow and a predictable access pattern, and the top levels of the
+    tree share a few cache lines. The bits of the index a search ends on
+    record every turn it took, so the answer is recovered with one shift.
+*/
+
+use std::borrow::Borrow;
+use std::ops::RangeBounds;
+
+use crate::ops::into_sorted;
+use crate::{range_predicates, AVLTreeSet, BalancePolicy};
+
+#[derive(Debug, Clone, PartialEq)]
+/// An immutable ordered set laid out for fast lookups. Made with `AVLTreeSet::freeze`.
+pub struct FrozenSet<T> {
+    /// Elements in breadth first order, the node with 1-based index k stored at k - 1
+    layout: Vec<T>,
+}
+
+impl<T: Ord, P: BalancePolicy> AVLTreeSet<T, P> {
+    /// Converts the set into a FrozenSet in O(n).
+    pub fn freeze(self) -> FrozenSet<T> {
+        let mut values = Vec::with_capacity(self.len());
+        into_sorted(self.root, &mut values);
+        let mut slots = (0..values.len()).map(|_| None).collect::<Vec<_>>();
+
+        // Visiting the slots in order places the sorted values
+        let mut index = first_index(slots.len());
+        for value in values {
+            slots[index - 1] = Some(value);
+            index = successor(index, slots.len());
+        }
+
+        FrozenSet {
+            layout: slots.into_iter().map(Option::unwrap).collect(),
+        }
+    }
+}
+
+/// 1-based index of the first node in order, or 0 for an empty tree.
+fn first_index(len: usize) -> usize {
+    // The leftmost node is on the deepest level
+    if len == 0 {
+        0
+    } else {
+        1 << len.ilog2()
+    }
+}
+
+/// 1-based index of the node after index in order, or 0 if it is the last.
+fn successor(index: usize, len: usize) -> usize {
+    if 2 * index < len {
+        // Leftmost node of the right subtree
+        let mut index = 2 * index + 1;
+        while 2 * index <= len {
+            index *= 2;
+        }
+        index
+    } else {
+        // Climb past every ancestor whose right subtree we were in, then one more
+        index >> (index.trailing_ones() + 1)
+    }
+}
+
+impl<T: Ord> FrozenSet<T> {
+    /// Returns the number of elements in the set.
+    pub fn len(&self) -> usize {
+        self.layout.len()
+    }
+
+    /// Returns true if the set contains no elements.
+    pub fn is_empty(&self) -> bool {
+        self.layout.is_empty()
+    }
+
+    /// Converts the set back into an AVLTreeSet in O(n).
+    pub fn thaw(self) -> AVLTreeSet<T> {
+        let len = self.layout.len();
+        let mut slots = self.layout.into_iter().map(Some).collect::<Vec<_>>();
+        let mut values = Vec::with_capacity(len);
+
+        let mut index = first_index(len);
+        while index != 0 {
+            values.push(slots[index - 1].take().unwrap());
+            index = successor(index, len);
+        }
+
+        AVLTreeSet::from_sorted(values)
+    }
+
+    /// Walks from the root going right past every element for which go_right is true,
+    /// returning the index the walk fell off the tree at.
+    ///
+    /// go_right must be true for every element up to some point and false after it.
+    fn descend(&self, go_right: impl Fn(&T) -> bool) -> usize {
+        let mut index = 1;
+        while index <= self.layout.len() {
+            index = 2 * index + usize::from(go_right(&self.layout[index - 1]));
+        }
+        index
+    }
+
+    /// 1-based index of the first element for which go_right is false, or 0 if there is none.
+    fn first_not(&self, go_right: impl Fn(&T) -> bool) -> usize {
+        // The last left turn was at that element
+        let index = self.descend(go_right);
+        index >> (index.trailing_ones() + 1)
+    }
+
+    /// 1-based index of the last element for which go_right is true, or 0 if there is none.
+    fn last(&self, go_right: impl Fn(&T) -> bool) -> usize {
+        // The last right turn was at that element
+        let index = self.descend(go_right);
+        index >> (index.trailing_zeros() + 1)
+    }
+
+    fn get(&self, index: usize) -> Option<&T> {
+        index.checked_sub(1).map(|index| &self.layout[index])
+    }
+
+    /// Returns true if the set contains an element equal to the value.
+    pub fn contains<Q>(&self, value: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.ceiling(value).is_some_and(|element| element.borrow() == value)
+    }
+
+    /// Returns the largest element less than or equal to value, if any.
+    pub fn floor<Q>(&self, value: &Q) -> Option<&T>
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.get(self.last(|element| element.borrow() <= value))
+    }
+
+    /// Returns the smallest element greater than or equal to value, if any.
+    pub fn ceiling<Q>(&self, value: &Q) -> Option<&T>
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.get(self.first_not(|element| element.borrow() < value))
+    }
+
+    /// Gets an iterator that visits the elements in ascending order.
+    pub fn iter(&self) -> FrozenSetRange<'_, T> {
+        FrozenSetRange {
+            set: self,
+            next: first_index(self.len()),
+            end: 0,
+        }
+    }
+
+    /// Gets an iterator that visits the elements in range in ascending order.
+    pub fn range<Q, R>(&self, range: R) -> FrozenSetRange<'_, T>
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+        R: RangeBounds<Q>,
+    {
+        let (before_start, before_end) = range_predicates(&range);
+        let start = self.first_not(before_start);
+        let end = self.first_not(&before_end);
+
+        // A start past the end of the range means it is empty
+        if !self.get(start).is_some_and(&before_end) {
+            return FrozenSetRange { set: self, next: 0, end: 0 };
+        }
+
+        FrozenSetRange { set: self, next: start, end }
+    }
+}
+
+/// An iterator over a range of a FrozenSet, in ascending order.
+pub struct FrozenSetRange<'a, T> {
+    set: &'a FrozenSet<T>,
+    /// 1-based index of the next element, 0 when done
+    next: usize,
+    /// 1-based index of the first element after the range, 0 for none
+    end: usize,
+}
+
+impl<'a, T> Iterator for FrozenSetRange<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        if self.next == self.end {
+            return None;
+        }
+
+        let element = &self.set.layout[self.next - 1];
+        self.next = successor(self.next, self.set.layout.len());
+        Some(element)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AvlPolicy, TreapPolicy, WeightBalancedPolicy};
+    use std::collections::BTreeSet;
+
+    fn frozen<P: BalancePolicy + Default>(xs: Vec<i16>, ys: Vec<i16>, a: i16, b: i16) -> bool {
+        let model = xs.iter().cloned().collect::<BTreeSet<_>>();
+        let frozen = xs.into_iter().collect::<AVLTreeSet<_, P>>().freeze();
+        let (low, high) = (a.min(b), a.max(b));
+
+        frozen.len() == model.len()
+            && frozen.iter().eq(model.iter())
+            && ys.iter().all(|y| {
+                frozen.contains(y) == model.contains(y)
+                    && frozen.floor(y) == model.range(..=y).next_back()
+                    && frozen.ceiling(y) == model.range(y..).next()
+            })
+            && frozen.range(low..high).eq(model.range(low..high))
+            && frozen.range(low..=high).eq(model.range(low..=high))
+            && frozen.range(low..).eq(model.range(low..))
+            && frozen.range(..high).eq(model.range(..high))
+            && frozen.range(high..low).eq(model.range(high..high))
+    }
+
+    #[quickcheck]
+    fn frozen_parity(xs: Vec<i16>, ys: Vec<i16>, a: i16, b: i16) -> bool {
+        frozen::<AvlPolicy>(xs, ys, a, b)
+    }
+
+    #[quickcheck]
+    fn weight_balanced_frozen_parity(xs: Vec<i16>, ys: Vec<i16>, a: i16, b: i16) -> bool {
+        frozen::<WeightBalancedPolicy>(xs, ys, a, b)
+    }
+
+    #[quickcheck]
+    fn treap_frozen_parity(xs: Vec<i16>, ys: Vec<i16>, a: i16, b: i16) -> bool {
+        frozen::<TreapPolicy>(xs, ys, a, b)
+    }
+
+    fn thaw<P: BalancePolicy + Default + Clone>(xs: Vec<i16>) -> bool {
+        let set = xs.into_iter().collect::<AVLTreeSet<_, P>>();
+        let thawed = set.clone().freeze().thaw();
+
+        thawed.validate().is_ok() && thawed.iter().eq(set.iter())
+    }
+
+    #[quickcheck]
+    fn thaw_round_trip(xs: Vec<i16>) -> bool {
+        thaw::<AvlPolicy>(xs)
+    }
+
+    #[quickcheck]
+    fn weight_balanced_thaw_round_trip(xs: Vec<i16>) -> bool {
+        thaw::<WeightBalancedPolicy>(xs)
+    }
+
+    #[quickcheck]
+    fn treap_thaw_round_trip(xs: Vec<i16>) -> bool {
+        thaw::<TreapPolicy>(xs)
+    }
+
+    #[test]
+    fn layout_is_breadth_first() {
+        let frozen = (1..=7).collect::<AVLTreeSet<_>>().freeze();
+
+        assert_eq!(frozen.layout, [4, 2, 6, 1, 3, 5, 7]);
+        assert_eq!(frozen.floor(&0), None);
+        assert_eq!(frozen.ceiling(&8), None);
+        assert!(frozen.contains(&5));
+        assert!(AVLTreeSet::<u8>::new().freeze().iter().next().is_none());
+    }
+}
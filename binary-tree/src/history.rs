@@ -0,0 +1,389 @@
+/*
+    Undo, redo and transactions for AVLTreeSet.
+    Every change is recorded together with what it takes to invert it,
+    so rolling back replays inverse operations instead of copying the tree.
+*/
+
+use std::error::Error;
+use std::fmt;
+
+use crate::AVLTreeSet;
+
+#[derive(Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Clone, Copy)]
+/// Identifies a point in the history of a VersionedSet.
+pub struct VersionId(u64);
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+/// Reasons a history operation could not be done.
+pub enum HistoryError {
+    /// The version was never created, or the changes after it were discarded.
+    UnknownVersion(VersionId),
+    /// Commit or abort was called without a transaction.
+    NoTransaction,
+    /// The version lies before the start of the open transaction.
+    OutsideTransaction(VersionId),
+}
+
+impl fmt::Display for HistoryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HistoryError::UnknownVersion(version) => write!(f, "unknown version {}", version.0),
+            HistoryError::NoTransaction => write!(f, "no open transaction"),
+            HistoryError::OutsideTransaction(version) => {
+                write!(f, "version {} is before the open transaction", version.0)
+            }
+        }
+    }
+}
+
+impl Error for HistoryError {}
+
+#[derive(Debug, PartialEq, Clone)]
+/// A recorded change to the set.
+enum Change<T> {
+    Inserted(T),
+    Taken(T),
+}
+
+#[derive(Debug, Clone)]
+/// An AVLTreeSet that records its changes so they can be undone and redone.
+///
+/// Rolling back restores the contents of the set, while the shape of the tree may differ.
+pub struct VersionedSet<T: Ord + Clone> {
+    set: AVLTreeSet<T>,
+    /// Applied changes, oldest first
+    done: Vec<Change<T>>,
+    /// Undone changes, most recently undone last
+    undone: Vec<Change<T>>,
+    /// Versions and the number of applied changes they stand for
+    checkpoints: Vec<(VersionId, usize)>,
+    next_version: u64,
+    /// Number of applied changes at the start of each open transaction
+    transactions: Vec<usize>,
+}
+
+impl<T: Ord + Clone> Default for VersionedSet<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Ord + Clone> VersionedSet<T> {
+    /// Creates a new, empty VersionedSet.
+    pub fn new() -> Self {
+        Self::from_set(AVLTreeSet::new())
+    }
+
+    /// Wraps an existing set. Its current contents are the start of the history.
+    pub fn from_set(set: AVLTreeSet<T>) -> Self {
+        Self {
+            set,
+            done: Vec::new(),
+            undone: Vec::new(),
+            checkpoints: Vec::new(),
+            next_version: 0,
+            transactions: Vec::new(),
+        }
+    }
+
+    /// Adds a value to the set, recording the change.
+    ///
+    /// Returns true if the set did not have an equal element present.
+    pub fn insert(&mut self, value: T) -> bool {
+        if !self.set.insert(value.clone()) {
+            return false;
+        }
+
+        self.record(Change::Inserted(value));
+        true
+    }
+
+    /// Removes and returns the element equal to the value, recording the change.
+    pub fn take(&mut self, value: &T) -> Option<T> {
+        let taken = self.set.take(value)?;
+
+        self.record(Change::Taken(taken.clone()));
+        Some(taken)
+    }
+
+    /// Returns true if the set contains an element equal to the value.
+    pub fn contains(&self, value: &T) -> bool {
+        self.set.contains(value)
+    }
+
+    /// Returns the number of elements in the set.
+    pub fn len(&self) -> usize {
+        self.set.len()
+    }
+
+    /// Returns true if the set contains no elements.
+    pub fn is_empty(&self) -> bool {
+        self.set.is_empty()
+    }
+
+    /// Gets an iterator that visits the elements in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = &'_ T> + '_ {
+        self.set.iter()
+    }
+
+    /// Returns the current set.
+    pub fn as_set(&self) -> &AVLTreeSet<T> {
+        &self.set
+    }
+
+    /// Returns the current set, dropping the history.
+    pub fn into_set(self) -> AVLTreeSet<T> {
+        self.set
+    }
+
+    /// Marks the current contents so they can be returned to with rollback_to.
+    pub fn checkpoint(&mut self) -> VersionId {
+        let version = VersionId(self.next_version);
+        self.next_version += 1;
+        self.checkpoints.push((version, self.done.len()));
+        version
+    }
+
+    /// Returns the set to the contents it had at version, undoing or redoing changes as needed.
+    ///
+    /// Inside a transaction only versions created since it began can be reached.
+    pub fn rollback_to(&mut self, version: VersionId) -> Result<(), HistoryError> {
+        let position = self
+            .checkpoints
+            .iter()
+            .find(|(id, _)| *id == version)
+            .map(|&(_, position)| position)
+            .ok_or(HistoryError::UnknownVersion(version))?;
+
+        if self.transactions.last().is_some_and(|&start| position < start) {
+            return Err(HistoryError::OutsideTransaction(version));
+        }
+
+        while self.done.len() > position {
+            self.undo_change();
+        }
+        while self.done.len() < position {
+            self.redo_change();
+        }
+
+        Ok(())
+    }
+
+    /// Undoes the most recent change. Returns false if there is nothing to undo.
+    ///
+    /// Inside a transaction only its own changes can be undone.
+    pub fn undo(&mut self) -> bool {
+        let start = self.transactions.last().copied().unwrap_or(0);
+        if self.done.len() <= start {
+            return false;
+        }
+
+        self.undo_change();
+        true
+    }
+
+    /// Redoes the most recently undone change. Returns false if there is nothing to redo.
+    pub fn redo(&mut self) -> bool {
+        if self.undone.is_empty() {
+            return false;
+        }
+
+        self.redo_change();
+        true
+    }
+
+    /// Starts a transaction. Transactions can be nested.
+    pub fn begin(&mut self) {
+        self.transactions.push(self.done.len());
+    }
+
+    /// Keeps the changes made since the matching begin.
+    pub fn commit(&mut self) -> Result<(), HistoryError> {
+        self.transactions.pop().ok_or(HistoryError::NoTransaction)?;
+        Ok(())
+    }
+
+    /// Reverts and discards every change made since the matching begin.
+    pub fn abort(&mut self) -> Result<(), HistoryError> {
+        let start = self.transactions.pop().ok_or(HistoryError::NoTransaction)?;
+
+        while self.done.len() > start {
+            self.undo_change();
+        }
+
+        // The aborted changes can not be redone, and neither can what was undone before
+        self.undone.clear();
+        self.checkpoints.retain(|&(_, position)| position <= start);
+
+        Ok(())
+    }
+
+    /// Forgets all recorded changes and checkpoints, keeping the current contents.
+    ///
+    /// Open transactions are committed.
+    pub fn clear_history(&mut self) {
+        self.done.clear();
+        self.undone.clear();
+        self.checkpoints.clear();
+        self.transactions.clear();
+    }
+
+    /// Records a change that was just applied. Anything that could be redone is discarded.
+    fn record(&mut self, change: Change<T>) {
+        if !self.undone.is_empty() {
+            self.undone.clear();
+            let position = self.done.len();
+            self.checkpoints.retain(|&(_, checkpoint)| checkpoint <= position);
+        }
+
+        self.done.push(change);
+    }
+
+    fn undo_change(&mut self) {
+        let change = self.done.pop().unwrap();
+        match &change {
+            Change::Inserted(value) => {
+                self.set.take(value);
+            }
+            Change::Taken(value) => {
+                self.set.insert(value.clone());
+            }
+        }
+        self.undone.push(change);
+    }
+
+    fn redo_change(&mut self) {
+        let change = self.undone.pop().unwrap();
+        match &change {
+            Change::Inserted(value) => {
+                self.set.insert(value.clone());
+            }
+            Change::Taken(value) => {
+                self.set.take(value);
+            }
+        }
+        self.done.push(change);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Applies operations, a positive number inserts it and a negative takes its absolute value.
+    fn apply(set: &mut VersionedSet<u32>, ops: &[i32]) {
+        for &op in ops {
+            if op >= 0 {
+                set.insert(op.unsigned_abs());
+            } else {
+                set.take(&op.unsigned_abs());
+            }
+        }
+    }
+
+    fn contents(set: &VersionedSet<u32>) -> Vec<u32> {
+        set.iter().cloned().collect()
+    }
+
+    #[quickcheck]
+    fn rollback_restores_checkpoints(batches: Vec<Vec<i32>>, targets: Vec<usize>) -> bool {
+        let mut set = VersionedSet::new();
+        let mut versions = Vec::new();
+
+        for batch in &batches {
+            versions.push((set.checkpoint(), contents(&set)));
+            apply(&mut set, batch);
+        }
+        versions.push((set.checkpoint(), contents(&set)));
+
+        targets.into_iter().all(|target| {
+            let (version, expected) = &versions[target % versions.len()];
+            set.rollback_to(*version).unwrap();
+            set.as_set().validate().is_ok() && contents(&set) == *expected
+        })
+    }
+
+    #[quickcheck]
+    fn undo_redo_round_trip(ops: Vec<i32>, steps: u8) -> bool {
+        let mut set = VersionedSet::new();
+        apply(&mut set, &ops);
+        let before = contents(&set);
+
+        let undone = (0..steps).take_while(|_| set.undo()).count();
+        let redone = (0..steps).take_while(|_| set.redo()).count();
+
+        undone == redone && set.as_set().validate().is_ok() && contents(&set) == before
+    }
+
+    #[quickcheck]
+    fn abort_restores_contents(initial: Vec<i32>, ops: Vec<i32>, nested: Vec<i32>) -> bool {
+        let mut set = VersionedSet::new();
+        apply(&mut set, &initial);
+        let before = contents(&set);
+
+        set.begin();
+        apply(&mut set, &ops);
+        set.begin();
+        apply(&mut set, &nested);
+        set.commit().unwrap();
+        set.abort().unwrap();
+
+        set.as_set().validate().is_ok() && contents(&set) == before && !set.redo()
+    }
+
+    #[quickcheck]
+    fn commit_keeps_changes(initial: Vec<i32>, ops: Vec<i32>) -> bool {
+        let mut set = VersionedSet::new();
+        apply(&mut set, &initial);
+
+        set.begin();
+        apply(&mut set, &ops);
+        let after = contents(&set);
+        set.commit().unwrap();
+
+        contents(&set) == after
+    }
+
+    #[test]
+    fn new_changes_discard_redo() {
+        let mut set = VersionedSet::new();
+        set.insert(1);
+        let version = set.checkpoint();
+        set.insert(2);
+        let later = set.checkpoint();
+
+        set.rollback_to(version).unwrap();
+        assert_eq!(contents(&set), [1]);
+
+        // Rolling forward works until a new change is made
+        set.rollback_to(later).unwrap();
+        assert_eq!(contents(&set), [1, 2]);
+        set.rollback_to(version).unwrap();
+        set.insert(3);
+
+        assert!(!set.redo());
+        assert_eq!(set.rollback_to(later), Err(HistoryError::UnknownVersion(later)));
+        assert_eq!(contents(&set), [1, 3]);
+    }
+
+    #[test]
+    fn transactions_are_bounded() {
+        let mut set = VersionedSet::new();
+        set.insert(1);
+        let outside = set.checkpoint();
+
+        set.begin();
+        set.insert(2);
+        assert!(set.undo());
+        assert!(!set.undo());
+        assert_eq!(set.rollback_to(outside), Ok(()));
+
+        set.insert(3);
+        set.take(&1);
+        assert_eq!(contents(&set), [3]);
+        set.abort().unwrap();
+
+        assert_eq!(contents(&set), [1]);
+        assert_eq!(set.commit(), Err(HistoryError::NoTransaction));
+    }
+}
@@ -0,0 +1,259 @@
+/*
+    Structural invariant checks for the AVL Tree.
+*/
+
+use std::cmp::max;
+use std::error::Error;
+use std::fmt;
+
+use crate::{AVLTree, AVLTreeSet, BalancePolicy};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+/// Which child to follow when walking down from the root.
+pub enum Direction {
+    Left,
+    Right,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+/// A broken invariant found by `AVLTreeSet::validate`.
+///
+/// Node positions are given as the path of directions taken from the root,
+/// an empty path being the root itself.
+pub enum InvariantError {
+    /// The node is not strictly between its ancestors' values.
+    Unordered { path: Vec<Direction> },
+    /// The stored height does not match the height of the subtree.
+    HeightMismatch { path: Vec<Direction>, stored: usize, actual: usize },
+    /// The stored size does not match the number of nodes in the subtree.
+    SizeMismatch { path: Vec<Direction>, stored: usize, actual: usize },
+    /// The heights of the node's children differ by two or more.
+    Unbalanced { path: Vec<Direction>, balance_factor: isize },
+    /// The stored length does not match the number of nodes.
+    LengthMismatch { stored: usize, actual: usize },
+    /// The node breaks the rule of the set's balance policy.
+    PolicyViolated { path: Vec<Direction> },
+}
+
+impl fmt::Display for InvariantError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InvariantError::Unordered { path } => {
+                write!(f, "node at {} breaks search tree order", PathDisplay(path))
+            }
+            InvariantError::HeightMismatch { path, stored, actual } => write!(
+                f,
+                "node at {} has stored height {} but actual height {}",
+                PathDisplay(path),
+                stored,
+                actual
+            ),
+            InvariantError::SizeMismatch { path, stored, actual } => write!(
+                f,
+                "node at {} has stored size {} but actual size {}",
+                PathDisplay(path),
+                stored,
+                actual
+            ),
+            InvariantError::Unbalanced { path, balance_factor } => write!(
+                f,
+                "node at {} has balance factor {}",
+                PathDisplay(path),
+                balance_factor
+            ),
+            InvariantError::LengthMismatch { stored, actual } => {
+                write!(f, "set has stored length {} but {} nodes", stored, actual)
+            }
+            InvariantError::PolicyViolated { path } => {
+                write!(f, "node at {} breaks the balance policy", PathDisplay(path))
+            }
+        }
+    }
+}
+
+impl Error for InvariantError {}
+
+/// Formats a path as `root/L/R/...`
+struct PathDisplay<'a>(&'a [Direction]);
+
+impl fmt::Display for PathDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "root")?;
+        for direction in self.0 {
+            match direction {
+                Direction::Left => write!(f, "/L")?,
+                Direction::Right => write!(f, "/R")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<T: Ord, P: BalancePolicy> AVLTreeSet<T, P> {
+    /// Checks that the set is a valid AVL Tree.
+    ///
+    /// Verifies search tree ordering, stored heights and sizes, the balance policy's rule and the stored length.
+    /// Returns the first broken invariant found.
+    pub fn validate(&self) -> Result<(), InvariantError> {
+        let mut path = Vec::new();
+        let (_, count) = validate_tree(&self.root, None, None, &mut path)?;
+
+        if count != self.len {
+            return Err(InvariantError::LengthMismatch {
+                stored: self.len,
+                actual: count,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Panics if the set is not a valid AVL Tree.
+    #[cfg(feature = "debug-invariants")]
+    pub(crate) fn assert_invariants(&self) {
+        if let Err(err) = self.validate() {
+            panic!("AVL invariant violated: {}", err);
+        }
+    }
+}
+
+/// Validates a subtree whose values must lie strictly between `lower` and `upper`.
+/// Returns the actual height and size of the subtree.
+fn validate_tree<T: Ord, P: BalancePolicy>(
+    tree: &AVLTree<T, (), P>,
+    lower: Option<&T>,
+    upper: Option<&T>,
+    path: &mut Vec<Direction>,
+) -> Result<(usize, usize), InvariantError> {
+    let node = match tree {
+        Some(node) => node,
+        None => return Ok((0, 0)),
+    };
+
+    let above_lower = lower.is_none_or(|lower| lower < &node.value);
+    let below_upper = upper.is_none_or(|upper| &node.value < upper);
+    if !above_lower || !below_upper {
+        return Err(InvariantError::Unordered { path: path.clone() });
+    }
+
+    path.push(Direction::Left);
+    let (left_height, left_size) = validate_tree(&node.left, lower, Some(&node.value), path)?;
+    path.pop();
+
+    path.push(Direction::Right);
+    let (right_height, right_size) = validate_tree(&node.right, Some(&node.value), upper, path)?;
+    path.pop();
+
+    let actual = 1 + max(left_height, right_height);
+    if node.height != actual {
+        return Err(InvariantError::HeightMismatch {
+            path: path.clone(),
+            stored: node.height,
+            actual,
+        });
+    }
+
+    let actual_size = 1 + left_size + right_size;
+    if node.size != actual_size {
+        return Err(InvariantError::SizeMismatch {
+            path: path.clone(),
+            stored: node.size,
+            actual: actual_size,
+        });
+    }
+
+    P::check(node, path)?;
+
+    Ok((actual, actual_size))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AVLNode;
+
+    #[quickcheck]
+    fn valid_after_insert(xs: Vec<i32>) -> bool {
+        let set = xs.into_iter().collect::<AVLTreeSet<_>>();
+        set.validate().is_ok()
+    }
+
+    #[quickcheck]
+    fn valid_after_take(xs: Vec<i32>, ys: Vec<i32>) -> bool {
+        let mut set = xs.into_iter().collect::<AVLTreeSet<_>>();
+
+        for y in ys {
+            set.take(&y);
+        }
+
+        set.validate().is_ok()
+    }
+
+    #[test]
+    fn detects_unordered() {
+        let mut set = (1..4).collect::<AVLTreeSet<u8>>();
+        set.root.as_mut().unwrap().left.as_mut().unwrap().value = 5;
+
+        assert_eq!(
+            set.validate(),
+            Err(InvariantError::Unordered { path: vec![Direction::Left] })
+        );
+    }
+
+    #[test]
+    fn detects_height_mismatch() {
+        let mut set = (1..4).collect::<AVLTreeSet<u8>>();
+        set.root.as_mut().unwrap().right.as_mut().unwrap().height = 3;
+
+        assert_eq!(
+            set.validate(),
+            Err(InvariantError::HeightMismatch {
+                path: vec![Direction::Right],
+                stored: 3,
+                actual: 1
+            })
+        );
+    }
+
+    #[test]
+    fn detects_size_mismatch() {
+        let mut set = (1..4).collect::<AVLTreeSet<u8>>();
+        set.root.as_mut().unwrap().size = 2;
+
+        assert_eq!(
+            set.validate(),
+            Err(InvariantError::SizeMismatch { path: vec![], stored: 2, actual: 3 })
+        );
+    }
+
+    #[test]
+    fn detects_unbalanced() {
+        let mut set = AVLTreeSet::new();
+        let mut root = AVLNode::new(1u8);
+        let mut right = AVLNode::new(2);
+        right.right = Some(Box::new(AVLNode::new(3)));
+        right.height = 2;
+        right.size = 2;
+        root.right = Some(Box::new(right));
+        root.height = 3;
+        root.size = 3;
+        set.root = Some(Box::new(root));
+        set.len = 3;
+
+        assert_eq!(
+            set.validate(),
+            Err(InvariantError::Unbalanced { path: vec![], balance_factor: -2 })
+        );
+    }
+
+    #[test]
+    fn detects_length_mismatch() {
+        let mut set = (1..4).collect::<AVLTreeSet<u8>>();
+        set.len = 7;
+
+        assert_eq!(
+            set.validate(),
+            Err(InvariantError::LengthMismatch { stored: 7, actual: 3 })
+        );
+    }
+}
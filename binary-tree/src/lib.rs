@@ -4,29 +4,148 @@
     Inspired by https://francismurillo.github.io/2019-07-31-Understanding-Rust-Through-AVL-Trees/
 */
 
+use std::borrow::Borrow;
 use std::cmp::{max, Ordering};
 use std::mem::{replace, swap};
+use std::ops::{Bound, RangeBounds};
+use std::ptr;
+
+/// Adds to one of the rebalancing counters, if the `stats` feature is enabled.
+macro_rules! count {
+    ($counter:ident) => {
+        count!($counter, 1)
+    };
+    ($counter:ident, $amount:expr) => {
+        #[cfg(feature = "stats")]
+        $crate::stats::record(|counters| counters.$counter += $amount as u64);
+    };
+}
 
+mod balance;
+mod batch;
+mod bimap;
+mod drain;
+mod durable;
+mod expiring;
+mod frozen;
+mod history;
+mod invariants;
+mod lib_v1;
+mod map;
+mod merge;
+mod merkle;
+mod nearest;
+mod ops;
+mod ordered;
+mod priority;
+mod range_set;
+#[cfg(feature = "rayon")]
+mod parallel;
+mod prefix;
+mod rope;
+mod seq;
+mod sliding;
+mod snapshot;
+#[cfg(feature = "stats")]
+pub mod stats;
+
+use ops::Augment;
+
+pub use balance::{AvlPolicy, BalancePolicy, TreapPolicy, WeightBalancedPolicy};
+pub use bimap::{AVLBiMap, Overwritten};
+pub use drain::AVLTreeSetDrain;
+pub use durable::{DurableError, DurableSet};
+pub use expiring::{Clock, ExpiringSet, ManualClock, SystemClock};
+pub use frozen::{FrozenSet, FrozenSetRange};
+pub use history::{HistoryError, VersionId, VersionedSet};
+pub use invariants::{Direction, InvariantError};
+pub use lib_v1::RedBlackTree;
+pub use map::{AVLTreeMap, AVLTreeMapIterMut};
+pub use merge::{merge_iter, MergeIter, TaggedMergeIter};
+pub use merkle::{MerkleDiff, MerkleSet};
+pub use nearest::{Distance, Nearest, TotalF32, TotalF64};
+pub use ordered::{OrderedMap, OrderedSet};
+pub use priority::AVLPriorityQueue;
+pub use range_set::AVLRangeSet;
+#[cfg(feature = "rayon")]
+pub use parallel::AVLTreeSetParIter;
+pub use rope::Rope;
+pub use seq::AVLSeq;
+pub use sliding::SlidingQuantile;
+pub use snapshot::{ElementCodec, SnapshotError};
 
 #[derive(Debug, PartialEq, Clone)]
 /// A single node in the AVL Tree.
-pub struct AVLNode<T: Ord> {
+pub struct AVLNode<T, A = (), P: BalancePolicy = AvlPolicy> {
     value: T,
-    left: AVLTree<T>,
-    right: AVLTree<T>,
+    left: AVLTree<T, A, P>,
+    right: AVLTree<T, A, P>,
     height: usize,
+    size: usize,
+    summary: A,
+    meta: P::Meta,
 }
 
-impl <T: Ord> AVLNode<T> {
+impl <T, A: Augment<T>> AVLNode<T, A> {
     /// Creates a new AVLNode with given value T 
     fn new(value: T) -> Self {
+        Self::with_meta(value, ())
+    }
+}
+
+impl<T, A: Augment<T>, P: BalancePolicy> AVLNode<T, A, P> {
+    /// Creates a new leaf holding value and the policy's metadata for it
+    fn with_meta(value: T, meta: P::Meta) -> Self {
         Self {
+            summary: A::compute(&value, None, None),
             value,
             height: 1,
+            size: 1,
             left: None,
             right: None,
+            meta,
         }
     }
+
+    /// Returns the value stored in the node.
+    pub fn value(&self) -> &T {
+        &self.value
+    }
+
+    /// Returns the left child, if any.
+    pub fn left(&self) -> Option<&Self> {
+        self.left.as_deref()
+    }
+
+    /// Returns the right child, if any.
+    pub fn right(&self) -> Option<&Self> {
+        self.right.as_deref()
+    }
+
+    /// Returns the left child for rotating, if any.
+    pub fn left_mut(&mut self) -> Option<&mut Self> {
+        self.left.as_deref_mut()
+    }
+
+    /// Returns the right child for rotating, if any.
+    pub fn right_mut(&mut self) -> Option<&mut Self> {
+        self.right.as_deref_mut()
+    }
+
+    /// Returns the number of levels in the subtree.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Returns the number of nodes in the subtree.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Returns the balance policy's metadata.
+    pub fn meta(&self) -> &P::Meta {
+        &self.meta
+    }
     
     /*
             r                        L
@@ -41,8 +160,8 @@ impl <T: Ord> AVLNode<T> {
         LR = left right
         R = right
     */
-    /// Rotate tree right around node
-    fn rotate_right(&mut self) -> bool {
+    /// Rotate tree right around node. Returns false if there is no left child.
+    pub fn rotate_right(&mut self) -> bool {
         if self.left.is_none() {
             return false;
         }
@@ -63,6 +182,7 @@ impl <T: Ord> AVLNode<T> {
                                             .as_mut()
                                             .unwrap()
                                             .value);
+        swap(&mut self.meta, &mut new_right_tree.as_mut().unwrap().meta);
         
         // Take right side
         let right = self.right.take();
@@ -86,6 +206,8 @@ impl <T: Ord> AVLNode<T> {
         // Update own height
         self.update_height();
 
+        count!(right_rotations);
+
         true
     }
 
@@ -102,8 +224,8 @@ impl <T: Ord> AVLNode<T> {
         RL = right left
         RR = right right
     */
-    /// Rotate tree left around node
-    fn rotate_left(&mut self) -> bool {
+    /// Rotate tree left around node. Returns false if there is no right child.
+    pub fn rotate_left(&mut self) -> bool {
         if self.right.is_none() {
             return false;
         }
@@ -124,6 +246,7 @@ impl <T: Ord> AVLNode<T> {
                                             .as_mut()
                                             .unwrap()
                                             .value);
+        swap(&mut self.meta, &mut new_left_tree.as_mut().unwrap().meta);
         
         // Take left side
         let left = self.left.take();
@@ -147,6 +270,8 @@ impl <T: Ord> AVLNode<T> {
         // Update own height
         self.update_height();
 
+        count!(left_rotations);
+
         true
     }
 
@@ -158,6 +283,9 @@ impl <T: Ord> AVLNode<T> {
 
                 if right_node.balance_factor() == 1 {
                     right_node.rotate_right();
+                    count!(double_rotations);
+                } else {
+                    count!(single_rotations);
                 }
 
                 self.rotate_left();
@@ -169,6 +297,9 @@ impl <T: Ord> AVLNode<T> {
                 
                 if left_node.balance_factor() == -1 {
                     left_node.rotate_left();
+                    count!(double_rotations);
+                } else {
+                    count!(single_rotations);
                 }
 
                 self. rotate_right();
@@ -202,22 +333,44 @@ impl <T: Ord> AVLNode<T> {
         }
     }
 
+    /// Number of nodes on left side
+    fn left_size(&self) -> usize {
+        self.left.as_ref().map_or(0, |node| node.size)
+    }
+
+    /// Number of nodes on right side
+    fn right_size(&self) -> usize {
+        self.right.as_ref().map_or(0, |node| node.size)
+    }
+
+    /// Recalculate height, size and summary from the children
     fn update_height(&mut self) {
         self.height = 1 + max(self.left_height(), self.right_height());
+        self.size = 1 + self.left_size() + self.right_size();
+        self.summary = A::compute(
+            &self.value,
+            self.left.as_ref().map(|node| &node.summary),
+            self.right.as_ref().map(|node| &node.summary),
+        );
     }
 } 
  
-type AVLTree<T> = Option<Box<AVLNode<T>>>;
+type AVLTree<T, A = (), P = AvlPolicy> = Option<Box<AVLNode<T, A, P>>>;
 
 #[derive(Debug, PartialEq, Clone)]
 /// An ordered set based on a AVL Tree.
-pub struct AVLTreeSet<T: Ord> {
-    root: AVLTree<T>,
+///
+/// Kept balanced by the policy P. Operations that split and join whole
+/// subtrees rely on AVL heights, so they are fastest with `AvlPolicy`.
+pub struct AVLTreeSet<T: Ord, P: BalancePolicy = AvlPolicy> {
+    root: AVLTree<T, (), P>,
+    len: usize,
+    policy: P,
 }
 
-impl<T: Ord> Default for AVLTreeSet<T> {
+impl<T: Ord, P: BalancePolicy + Default> Default for AVLTreeSet<T, P> {
     fn default() -> Self {
-        Self::new()
+        Self::with_policy(P::default())
     }
 }
 
@@ -226,7 +379,36 @@ impl <T: Ord> AVLTreeSet<T> {
     /// 
     /// Does not allocate anything on its own.
     pub fn new() -> Self {
-        Self { root: None }
+        Self::with_policy(AvlPolicy)
+    }
+}
+
+impl<T: Ord, P: BalancePolicy> AVLTreeSet<T, P> {
+    /// Creates a new, empty AVLTreeSet balanced by policy.
+    pub fn with_policy(policy: P) -> Self {
+        Self { root: None, len: 0, policy }
+    }
+
+    /// Creates a set from values that are already in strictly ascending order, without comparing them.
+    ///
+    /// Takes O(n) with `AvlPolicy`.
+    pub(crate) fn from_sorted(values: Vec<T>) -> Self
+    where
+        P: Default,
+    {
+        let mut set = Self::default();
+        set.append_sorted(values);
+        set
+    }
+
+    /// Returns the number of elements in the set.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if the set contains no elements.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
     }
 
     /// Adds a value to the set.
@@ -234,36 +416,88 @@ impl <T: Ord> AVLTreeSet<T> {
     /// If the set did not have an equal element present, true is returned.
     /// 
     /// IF the set did have an equal element present, false is returned, and the enntry is nor updated.
+    ///
+    /// Comparisons are all made before the tree is changed, so if one panics the set is left as it was.
     pub fn insert(&mut self, value: T) -> bool {
+        let inserted = self.insert_by(value, |node, value| {
+            count!(comparisons);
+            node.value.cmp(value)
+        });
+
+        #[cfg(feature = "debug-invariants")]
+        self.assert_invariants();
+
+        inserted
+    }
+
+    /// Adds value at the leaf where direction leads, unless it returns Equal on the way.
+    ///
+    /// direction tells how a node is ordered relative to the value.
+    fn insert_by(&mut self, value: T, mut direction: impl FnMut(&AVLNode<T, (), P>, &T) -> Ordering) -> bool {
         let mut current_tree = &mut self.root;
-        let mut prev_ptrs = Vec::<*mut AVLNode<T>>::new();
+        let mut prev_ptrs = Vec::<*mut AVLNode<T, (), P>>::new();
         
         while let Some(current_node) = current_tree {
             prev_ptrs.push(&mut **current_node);
-            match current_node.value.cmp(&value) {
+            match direction(current_node, &value) {
                 Ordering::Greater => current_tree = &mut current_node.left,
                 Ordering::Equal => return false,
                 Ordering::Less => current_tree = &mut current_node.right, 
             }
         }
 
-        *current_tree = Some(Box::new(AVLNode::new(value)));
+        *current_tree = Some(Box::new(AVLNode::with_meta(value, self.policy.leaf())));
+
+        count!(inserts);
+        count!(insert_path_length, prev_ptrs.len() + 1);
 
         for ptr in prev_ptrs.into_iter().rev() {
             let node = unsafe { &mut *ptr };
             node.update_height();
-            node.rebalance();
+            self.policy.after_insert(node);
         }
 
+        self.len += 1;
+
         true
     }
 
+    /// Adds value at position index without comparing it to the elements.
+    fn insert_at(&mut self, mut index: usize, value: T) {
+        self.insert_by(value, |node, _| {
+            if index <= node.left_size() {
+                Ordering::Greater
+            } else {
+                index -= node.left_size() + 1;
+                Ordering::Less
+            }
+        });
+    }
+
+    /// Adds values, in strictly ascending order and above every element, without comparing them.
+    ///
+    /// Takes O(m + log n) for m values with `AvlPolicy`, and O(m log(n + m)) otherwise.
+    pub(crate) fn append_sorted(&mut self, values: Vec<T>) {
+        if let Some(root) = P::as_avl(&mut self.root) {
+            self.len += values.len();
+            *root = ops::join2(root.take(), ops::build(values));
+        } else {
+            for value in values {
+                self.insert_by(value, |_, _| Ordering::Less);
+            }
+        }
+    }
+
     /// Returns true if set contains an element equal to the value.
-    pub fn contains(&self, value: &T) -> bool {
+    pub fn contains<Q>(&self, value: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
         let mut current_tree = &self.root;
         
         while let Some(current_node) = current_tree {
-            match current_node.value.cmp(value) {
+            match current_node.value.borrow().cmp(value) {
                 Ordering::Greater => current_tree = &current_node.left,
                 Ordering::Equal => return true,
                 Ordering::Less => current_tree = &current_node.right, 
@@ -278,13 +512,54 @@ impl <T: Ord> AVLTreeSet<T> {
     }
 
     /// Removes and returns the element in the set, if any, that is equal to the value.
-    pub fn take(&mut self, value: &T) -> Option<T> {
+    ///
+    /// Comparisons are all made before the tree is changed, so if one panics the set is left as it was.
+    pub fn take<Q>(&mut self, value: &Q) -> Option<T>
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.take_by(|element| element.borrow().cmp(value))
+    }
+
+    /// Removes and returns the element for which compare returns Equal, if any.
+    ///
+    /// compare tells how an element is ordered relative to the one searched for.
+    pub(crate) fn take_by(&mut self, compare: impl Fn(&T) -> Ordering) -> Option<T> {
+        let taken = self.take_where(|node| {
+            count!(comparisons);
+            compare(&node.value)
+        });
+
+        #[cfg(feature = "debug-invariants")]
+        self.assert_invariants();
+
+        taken
+    }
+
+    /// Removes and returns the element at position index without comparing it to the others.
+    pub(crate) fn take_at(&mut self, mut index: usize) -> Option<T> {
+        self.take_where(|node| match index.cmp(&node.left_size()) {
+            Ordering::Less => Ordering::Greater,
+            Ordering::Equal => Ordering::Equal,
+            Ordering::Greater => {
+                index -= node.left_size() + 1;
+                Ordering::Less
+            }
+        })
+    }
+
+    /// Removes and returns the element of the node where direction returns Equal, if any.
+    ///
+    /// direction tells how a node is ordered relative to the one searched for.
+    fn take_where(&mut self, mut direction: impl FnMut(&AVLNode<T, (), P>) -> Ordering) -> Option<T> {
+        let policy = &mut self.policy;
         let mut current_tree = &mut self.root;
-        let mut prev_ptrs = Vec::<*mut AVLNode<T>>::new();
+        let mut prev_ptrs = Vec::<*mut AVLNode<T, (), P>>::new();
         let mut target_value = None;
         
         while let Some(current_node) = current_tree {
-            match current_node.value.cmp(value) {
+            match direction(current_node) {
                 Ordering::Greater => {
                     prev_ptrs.push(&mut **current_node);
                     current_tree = &mut current_node.left;
@@ -305,6 +580,9 @@ impl <T: Ord> AVLTreeSet<T> {
 
         let target_node = target_value.unwrap();
 
+        count!(takes);
+        count!(take_path_length, prev_ptrs.len() + 1);
+
         // Take value. Returning the value of the node and deleting it
         let taken_value = if target_node.left.is_none() || target_node.right.is_none() {
             // If node has one or zero children
@@ -319,20 +597,18 @@ impl <T: Ord> AVLTreeSet<T> {
                     let prev_node = unsafe { &mut *prev_ptr };
                     
                     // Check which of parent's children is target_node
+                    // Compare addresses rather than values, so no user code runs while restructuring
                     // Take that node
-                    let _value = if let Some(ref _left) = prev_node.left {
-                        if _left.value == target_node.value {
-                            prev_node.left.take().unwrap().value
-                        } else {
-                            prev_node.right.take().unwrap().value
-                        }
+                    let is_left = prev_node.left.as_deref().is_some_and(|left| ptr::eq(left, target_node));
+                    let _value = if is_left {
+                        prev_node.left.take().unwrap().value
                     } else {
                         prev_node.right.take().unwrap().value
                     };
 
                     // Update and rebalance parent
                     prev_node.update_height();
-                    prev_node.rebalance();
+                    policy.after_delete(prev_node);
 
                     _value
                 } else {
@@ -371,21 +647,22 @@ impl <T: Ord> AVLTreeSet<T> {
                 // If there is not a left child of the right child of target
                 let mut right_node = right_tree.take().unwrap();
                 
-                // Replace target with right node
+                // Replace target with right node, whose metadata goes with its value
                 let _value = replace(&mut target_node.value, right_node.value);
+                target_node.meta = right_node.meta;
 
                 // Replace right node with its right child if any
                 let _ = replace(&mut target_node.right, right_node.right.take());
 
                 // Update node
                 target_node.update_height();
-                target_node.rebalance();
+                policy.after_delete(target_node);
 
                 _value
             } else {
                 // If right child has a left child
                 let mut next_tree = right_tree;
-                let mut _prev_ptrs = Vec::<*mut AVLNode<T>>::new();
+                let mut _prev_ptrs = Vec::<*mut AVLNode<T, (), P>>::new();
     
                 // While there are children to the left
                 while let Some(_next_left) = next_tree {
@@ -401,23 +678,25 @@ impl <T: Ord> AVLTreeSet<T> {
     
                 // Replace target node with this leftmost child.
                 // Since it is easier to just switch value we do that instead.
+                // The metadata goes with the value.
                 let _value = replace(&mut target_node.value, leftmost.value);
+                target_node.meta = leftmost.meta;
     
                 // Replace the spot where leftmost was with its right child.
                 let _ = replace(&mut parent_left.left, leftmost.right.take());
     
                 // Update the nodes
                 parent_left.update_height();
-                parent_left.rebalance();
+                policy.after_delete(parent_left);
     
                 for ptr in _prev_ptrs.into_iter().rev() {
                     let node = unsafe { &mut *ptr };
                     node.update_height();
-                    node.rebalance();
+                    policy.after_delete(node);
                 }
                 
                 target_node.update_height();
-                target_node.rebalance();
+                policy.after_delete(target_node);
     
                 _value
             }
@@ -427,25 +706,220 @@ impl <T: Ord> AVLTreeSet<T> {
         for ptr in prev_ptrs.into_iter().rev() {
             let node = unsafe { &mut *ptr };
             node.update_height();
-            node.rebalance();
+            policy.after_delete(node);
         }
 
+        self.len -= 1;
+
         Some(taken_value)
     }
 
+    /// Returns a reference to the first element in the set, if any.
+    pub fn first(&self) -> Option<&T> {
+        let mut current_node = self.root.as_ref()?;
+
+        while let Some(left) = current_node.left.as_ref() {
+            current_node = left;
+        }
+
+        Some(&current_node.value)
+    }
+
+    /// Returns a reference to the last element in the set, if any.
+    pub fn last(&self) -> Option<&T> {
+        let mut current_node = self.root.as_ref()?;
+
+        while let Some(right) = current_node.right.as_ref() {
+            current_node = right;
+        }
+
+        Some(&current_node.value)
+    }
+
+    /// Gets a double ended iterator that visits the elements in range in ascending order.
+    ///
+    /// Finding both ends of the range takes O(log n).
+    pub fn range<Q, R>(&self, range: R) -> AVLTreeSetRange<'_, T, P>
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+        R: RangeBounds<Q>,
+    {
+        let (before_start, before_end) = range_predicates(&range);
+
+        self.range_by(before_start, before_end)
+    }
+
+    /// Gets an iterator over the elements for which before_start is false and before_end is true.
+    ///
+    /// Each must be true for every element up to some point and false after it.
+    pub(crate) fn range_by(
+        &self,
+        before_start: impl Fn(&T) -> bool,
+        before_end: impl Fn(&T) -> bool,
+    ) -> AVLTreeSetRange<'_, T, P> {
+        // Path to the first element in range, holding every node still to visit
+        let mut front = Vec::new();
+        let mut current_tree = &self.root;
+        while let Some(current_node) = current_tree {
+            if before_start(&current_node.value) {
+                current_tree = &current_node.right;
+            } else {
+                front.push(&**current_node);
+                current_tree = &current_node.left;
+            }
+        }
+
+        // Path to the last element in range
+        let mut back = Vec::new();
+        let mut current_tree = &self.root;
+        while let Some(current_node) = current_tree {
+            if before_end(&current_node.value) {
+                back.push(&**current_node);
+                current_tree = &current_node.right;
+            } else {
+                current_tree = &current_node.left;
+            }
+        }
+
+        AVLTreeSetRange {
+            front,
+            back,
+            remaining: self.rank(before_end).saturating_sub(self.rank(before_start)),
+        }
+    }
+
+    /// Number of elements for which before returns true.
+    ///
+    /// before must be true for every element up to some point and false after it.
+    pub(crate) fn rank(&self, before: impl Fn(&T) -> bool) -> usize {
+        let mut current_tree = &self.root;
+        let mut rank = 0;
+
+        while let Some(current_node) = current_tree {
+            if before(&current_node.value) {
+                rank += current_node.left_size() + 1;
+                current_tree = &current_node.right;
+            } else {
+                current_tree = &current_node.left;
+            }
+        }
+
+        rank
+    }
+
     /// An iterator over the nodes instead of the values they contain
-    fn node_iter(&self) -> impl Iterator<Item = &'_ AVLNode<T>> + '_ {
+    fn node_iter(&self) -> impl Iterator<Item = &'_ AVLNode<T, (), P>> + '_ {
         AVLTreeSetNodeIter {
             prev_nodes: Vec::default(),
             current_tree: &self.root,
         }
     }
+
+    /// Calls f with the element equal to value, if any, and returns its result.
+    ///
+    /// If f changes where the element belongs in the order, it is moved there.
+    /// If it then equals another element, it is dropped, as with insert.
+    /// If f or a comparison panics, the element is removed and dropped, so the set stays valid.
+    pub fn get_mut_with<Q, R>(&mut self, value: &Q, f: impl FnOnce(&mut T) -> R) -> Option<R>
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let index = self.rank(|element| element.borrow() < value);
+        ops::get_at(&self.root, index).filter(|node| node.value.borrow() == value)?;
+
+        // From here until the element is known to be in order, unwinding removes it
+        let mut guard = RemoveOnUnwind { set: self, index, armed: true };
+        let result = f(&mut ops::get_at_mut(&mut guard.set.root, index).unwrap().value);
+
+        // Still in order when it sits between its neighbours
+        let root = &guard.set.root;
+        let element = &ops::get_at(root, index).unwrap().value;
+        let after_previous = index == 0 || &ops::get_at(root, index - 1).unwrap().value < element;
+        let before_next = ops::get_at(root, index + 1).is_none_or(|next| element < &next.value);
+
+        guard.armed = false;
+        let set = &mut *guard.set;
+
+        if !(after_previous && before_next) {
+            let moved = set.take_at(index).unwrap();
+            set.insert(moved);
+        }
+
+        #[cfg(feature = "debug-invariants")]
+        set.assert_invariants();
+
+        Some(result)
+    }
+
+    /// Removes and returns the first element in the set, if any.
+    pub fn pop_first(&mut self) -> Option<T> {
+        let first = self.take_where(|node| match node.left {
+            Some(_) => Ordering::Greater,
+            None => Ordering::Equal,
+        });
+
+        #[cfg(feature = "debug-invariants")]
+        self.assert_invariants();
+
+        first
+    }
+
+    /// Removes and returns the last element in the set, if any.
+    pub fn pop_last(&mut self) -> Option<T> {
+        let last = self.take_where(|node| match node.right {
+            Some(_) => Ordering::Less,
+            None => Ordering::Equal,
+        });
+
+        #[cfg(feature = "debug-invariants")]
+        self.assert_invariants();
+
+        last
+    }
+}
+
+/// Predicates telling whether a value comes before the start of range and before its end.
+fn range_predicates<T, Q, R>(range: &R) -> (impl Fn(&T) -> bool + '_, impl Fn(&T) -> bool + '_)
+where
+    T: Borrow<Q>,
+    Q: Ord + ?Sized,
+    R: RangeBounds<Q>,
+{
+    let before_start = |value: &T| match range.start_bound() {
+        Bound::Included(start) => value.borrow() < start,
+        Bound::Excluded(start) => value.borrow() <= start,
+        Bound::Unbounded => false,
+    };
+    let before_end = |value: &T| match range.end_bound() {
+        Bound::Included(end) => value.borrow() <= end,
+        Bound::Excluded(end) => value.borrow() < end,
+        Bound::Unbounded => true,
+    };
+
+    (before_start, before_end)
+}
+
+/// Removes the element at index from the set if dropped while still armed, which only happens when unwinding.
+struct RemoveOnUnwind<'a, T: Ord, P: BalancePolicy> {
+    set: &'a mut AVLTreeSet<T, P>,
+    index: usize,
+    armed: bool,
+}
+
+impl<T: Ord, P: BalancePolicy> Drop for RemoveOnUnwind<'_, T, P> {
+    fn drop(&mut self) {
+        if self.armed {
+            self.set.take_at(self.index);
+        }
+    }
 }
 
-impl<T: Ord> FromIterator<T> for AVLTreeSet<T> {
+impl<T: Ord, P: BalancePolicy + Default> FromIterator<T> for AVLTreeSet<T, P> {
     /// Create an AVLTreeSet from an iterator.s
     fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
-        let mut set = Self::new();
+        let mut set = Self::default();
 
         for i in iter {
             set.insert(i);
@@ -456,13 +930,13 @@ impl<T: Ord> FromIterator<T> for AVLTreeSet<T> {
 }
 
 #[derive(Debug)]
-pub struct AVLTreeSetNodeIter<'a, T: Ord> {
-    prev_nodes: Vec<&'a AVLNode<T>>,
-    current_tree: &'a AVLTree<T>,
+pub struct AVLTreeSetNodeIter<'a, T, A = (), P: BalancePolicy = AvlPolicy> {
+    prev_nodes: Vec<&'a AVLNode<T, A, P>>,
+    current_tree: &'a AVLTree<T, A, P>,
 }
 
-impl<'a, T: 'a + Ord> Iterator for AVLTreeSetNodeIter<'a, T> {
-    type Item = &'a AVLNode<T>;
+impl<'a, T: 'a, A: 'a, P: BalancePolicy + 'a> Iterator for AVLTreeSetNodeIter<'a, T, A, P> {
+    type Item = &'a AVLNode<T, A, P>;
     fn next(&mut self) -> Option<Self::Item> {
         loop {
             match *self.current_tree {
@@ -497,6 +971,104 @@ impl<'a, T: 'a + Ord> Iterator for AVLTreeSetNodeIter<'a, T> {
     }
 }
 
+#[derive(Debug)]
+/// A double ended iterator over a range of elements in an AVLTreeSet.
+pub struct AVLTreeSetRange<'a, T, P: BalancePolicy = AvlPolicy> {
+    front: Vec<&'a AVLNode<T, (), P>>,
+    back: Vec<&'a AVLNode<T, (), P>>,
+    remaining: usize,
+}
+
+impl<T, P: BalancePolicy> AVLTreeSetRange<'_, T, P> {
+    /// Skips ahead to the first remaining element not less than bound.
+    ///
+    /// Walks on from the current position instead of from the root, so skipping d elements takes O(log d).
+    pub fn seek<Q>(&mut self, bound: &Q)
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let mut skipped = 0;
+
+        while let Some(&node) = self.front.last() {
+            if node.value.borrow() >= bound {
+                break;
+            }
+            self.front.pop();
+            skipped += 1;
+
+            // The right side comes before the next node on the stack, so if that is
+            // still below bound the whole right side is too
+            if self.front.last().is_some_and(|next| next.value.borrow() < bound) {
+                skipped += node.right_size();
+                continue;
+            }
+
+            // Otherwise the first element not below bound, if any, is on the right side
+            let mut current_tree = &node.right;
+            while let Some(current_node) = current_tree {
+                if current_node.value.borrow() < bound {
+                    skipped += current_node.left_size() + 1;
+                    current_tree = &current_node.right;
+                } else {
+                    self.front.push(current_node);
+                    current_tree = &current_node.left;
+                }
+            }
+            break;
+        }
+
+        self.remaining = self.remaining.saturating_sub(skipped);
+    }
+}
+
+impl<'a, T, P: BalancePolicy> Iterator for AVLTreeSetRange<'a, T, P> {
+    type Item = &'a T;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        let node = self.front.pop()?;
+        self.remaining -= 1;
+
+        // Next up is the leftmost node of the right side
+        let mut current_tree = &node.right;
+        while let Some(current_node) = current_tree {
+            self.front.push(current_node);
+            current_tree = &current_node.left;
+        }
+
+        Some(&node.value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<T, P: BalancePolicy> DoubleEndedIterator for AVLTreeSetRange<'_, T, P> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        let node = self.back.pop()?;
+        self.remaining -= 1;
+
+        // Next up from the back is the rightmost node of the left side
+        let mut current_tree = &node.left;
+        while let Some(current_node) = current_tree {
+            self.back.push(current_node);
+            current_tree = &current_node.right;
+        }
+
+        Some(&node.value)
+    }
+}
+
+impl<T, P: BalancePolicy> ExactSizeIterator for AVLTreeSetRange<'_, T, P> {}
+
 #[cfg(test)]
 extern crate quickcheck;
 #[cfg(test)]
@@ -504,12 +1076,15 @@ extern crate quickcheck;
 extern crate quickcheck_macros;
 
 #[cfg(test)]
+#[allow(clippy::nonminimal_bool)]
 mod tests {
     use super::*;
     use quickcheck::{Arbitrary, Gen, TestResult};
+    use std::cell::{Cell, RefCell};
     use std::collections::BTreeSet;
+    use std::panic::AssertUnwindSafe;
 
-    impl<T: Arbitrary + Ord> Arbitrary for AVLTreeSet<T> {
+    impl<T: Arbitrary + Ord, P: BalancePolicy + Default + Clone + 'static> Arbitrary for AVLTreeSet<T, P> {
         fn arbitrary(g: &mut Gen) -> Self {
             let vec: Vec<T> = Arbitrary::arbitrary(g);
             vec.into_iter().collect()
@@ -719,11 +1294,113 @@ mod tests {
         avl_set.insert(x) == btree_set.insert(x)
     }
     
+    #[quickcheck]
+    fn range_parity(xs: Vec<i16>, a: i16, b: i16) -> bool {
+        let avl_set = xs.iter().cloned().collect::<AVLTreeSet<_>>();
+        let btree_set = xs.iter().cloned().collect::<BTreeSet<_>>();
+        let (low, high) = (a.min(b), a.max(b));
+        let excluded = (Bound::Excluded(low), Bound::Included(high));
+
+        avl_set.range(low..high).eq(btree_set.range(low..high))
+            && avl_set.range(low..=high).rev().eq(btree_set.range(low..=high).rev())
+            && avl_set.range(..high).eq(btree_set.range(..high))
+            && avl_set.range(low..).eq(btree_set.range(low..))
+            && avl_set.range(excluded).eq(btree_set.range(excluded))
+            && avl_set.range(low..high).len() == btree_set.range(low..high).count()
+    }
+
+    #[quickcheck]
+    fn range_double_ended_parity(xs: Vec<i16>, fronts: Vec<bool>) -> bool {
+        let avl_set = xs.iter().cloned().collect::<AVLTreeSet<_>>();
+        let btree_set = xs.iter().cloned().collect::<BTreeSet<_>>();
+        let mut avl_range = avl_set.range(..);
+        let mut btree_range = btree_set.range(..);
+
+        fronts.into_iter().chain(std::iter::repeat_n(true, xs.len())).all(|front| {
+            if front {
+                avl_range.next() == btree_range.next()
+            } else {
+                avl_range.next_back() == btree_range.next_back()
+            }
+        })
+    }
+
+    #[quickcheck]
+    fn range_seek_parity(xs: Vec<i16>, steps: Vec<(bool, i16)>, from_back: u8) -> bool {
+        let set = xs.iter().cloned().collect::<AVLTreeSet<_>>();
+        let mut model = xs.iter().cloned().collect::<BTreeSet<_>>().into_iter().collect::<Vec<_>>();
+        let mut range = set.range(..);
+
+        // Taking from the back first checks that seeking stops at the back's position
+        let from_back = from_back as usize % (model.len() + 1);
+        for _ in 0..from_back {
+            range.next_back();
+        }
+        model.truncate(model.len() - from_back);
+        let mut model = model.into_iter().peekable();
+
+        steps.into_iter().all(|(seek, bound)| {
+            if seek {
+                range.seek(&bound);
+                while model.next_if(|&x| x < bound).is_some() {}
+                range.len() == model.len()
+            } else {
+                range.next() == model.next().as_ref()
+            }
+        }) && range.copied().eq(model)
+    }
+
+    #[quickcheck]
+    fn first_last_parity(xs: Vec<isize>) -> bool {
+        let avl_set = xs.iter().cloned().collect::<AVLTreeSet<_>>();
+        let btree_set = xs.iter().cloned().collect::<BTreeSet<_>>();
+
+        avl_set.first() == btree_set.first() && avl_set.last() == btree_set.last()
+    }
+
+    fn pop_first_last<P: BalancePolicy + Default>(xs: Vec<isize>, from_back: Vec<bool>) -> bool {
+        let mut avl_set = xs.iter().cloned().collect::<AVLTreeSet<_, P>>();
+        let mut btree_set = xs.iter().cloned().collect::<BTreeSet<_>>();
+
+        from_back.into_iter().all(|back| {
+            let popped = if back {
+                avl_set.pop_last() == btree_set.pop_last()
+            } else {
+                avl_set.pop_first() == btree_set.pop_first()
+            };
+
+            popped && avl_set.len() == btree_set.len() && avl_set.validate().is_ok()
+        }) && avl_set.iter().eq(btree_set.iter())
+    }
+
+    #[quickcheck]
+    fn pop_first_last_parity(xs: Vec<isize>, from_back: Vec<bool>) -> bool {
+        pop_first_last::<AvlPolicy>(xs, from_back)
+    }
+
+    #[quickcheck]
+    fn weight_balanced_pop_first_last_parity(xs: Vec<isize>, from_back: Vec<bool>) -> bool {
+        pop_first_last::<WeightBalancedPolicy>(xs, from_back)
+    }
+
+    #[quickcheck]
+    fn treap_pop_first_last_parity(xs: Vec<isize>, from_back: Vec<bool>) -> bool {
+        pop_first_last::<TreapPolicy>(xs, from_back)
+    }
+
+    #[test]
+    fn contains_borrowed() {
+        let set = ["a", "b"].iter().map(|s| s.to_string()).collect::<AVLTreeSet<_>>();
+
+        assert!(set.contains("a"));
+        assert!(!set.contains("c"));
+    }
+
     #[test]
     fn iter_insert() {
         let mut set = AVLTreeSet::new();
 
-        for i in (1..4 as usize).rev() {
+        for i in (1..4_usize).rev() {
             set.insert(i);
         }
 
@@ -733,4 +1410,224 @@ mod tests {
         assert_eq!(iter.next(), Some(&3));
         assert_eq!(iter.next(), None);
     }
-}
\ No newline at end of file
+
+    #[derive(Debug, Clone)]
+    struct Record {
+        id: u32,
+        hits: u32,
+    }
+
+    impl PartialEq for Record {
+        fn eq(&self, other: &Self) -> bool {
+            self.id == other.id
+        }
+    }
+
+    impl Eq for Record {}
+
+    impl PartialOrd for Record {
+        fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+            Some(self.cmp(other))
+        }
+    }
+
+    impl Ord for Record {
+        fn cmp(&self, other: &Self) -> Ordering {
+            self.id.cmp(&other.id)
+        }
+    }
+
+    #[test]
+    fn get_mut_with() {
+        let mut set = (0..10).map(|id| Record { id, hits: 0 }).collect::<AVLTreeSet<_>>();
+        let probe = |id| Record { id, hits: 0 };
+
+        // Non-key fields change in place
+        assert_eq!(set.get_mut_with(&probe(4), |record| { record.hits += 1; record.hits }), Some(1));
+        assert_eq!(set.iter().find(|record| record.id == 4).unwrap().hits, 1);
+        assert_eq!(set.get_mut_with(&probe(42), |_| ()), None);
+
+        // A changed key moves the element
+        set.get_mut_with(&probe(2), |record| record.id = 20);
+        assert!(set.validate().is_ok());
+        assert_eq!(set.last().unwrap().id, 20);
+        assert_eq!(set.len(), 10);
+
+        // A key equal to another element drops it
+        set.get_mut_with(&probe(3), |record| record.id = 5);
+        assert!(set.validate().is_ok());
+        assert_eq!(set.len(), 9);
+    }
+
+    fn get_mut_with_moves<P: BalancePolicy + Default>(xs: Vec<u16>, target: u16, moved: u16) -> bool {
+        let mut set = xs.iter().cloned().collect::<AVLTreeSet<_, P>>();
+        let mut model = xs.iter().cloned().collect::<BTreeSet<_>>();
+
+        let found = set.get_mut_with(&target, |value| *value = moved).is_some();
+        if model.remove(&target) {
+            model.insert(moved);
+        }
+
+        found == xs.contains(&target) && set.validate().is_ok() && set.iter().eq(model.iter())
+    }
+
+    #[quickcheck]
+    fn get_mut_with_parity(xs: Vec<u16>, target: u16, moved: u16) -> bool {
+        get_mut_with_moves::<AvlPolicy>(xs, target, moved)
+    }
+
+    #[quickcheck]
+    fn weight_balanced_get_mut_with_parity(xs: Vec<u16>, target: u16, moved: u16) -> bool {
+        get_mut_with_moves::<WeightBalancedPolicy>(xs, target, moved)
+    }
+
+    #[quickcheck]
+    fn treap_get_mut_with_parity(xs: Vec<u16>, target: u16, moved: u16) -> bool {
+        get_mut_with_moves::<TreapPolicy>(xs, target, moved)
+    }
+
+    thread_local! {
+        /// Comparisons left before the next one panics, if armed
+        pub(crate) static FUSE: Cell<Option<u8>> = const { Cell::new(None) };
+        /// Value whose drop panics, if armed
+        pub(crate) static DROP_FUSE: Cell<Option<u8>> = const { Cell::new(None) };
+        /// Ids of the Bombs dropped so far
+        pub(crate) static DROPS: RefCell<Vec<usize>> = const { RefCell::new(Vec::new()) };
+    }
+
+    #[derive(Debug)]
+    /// An element whose comparisons and drop can be made to panic, and which records its drops.
+    pub(crate) struct Bomb {
+        pub(crate) value: u8,
+        id: usize,
+    }
+
+    impl Bomb {
+        pub(crate) fn new(value: u8, ids: &mut usize) -> Self {
+            *ids += 1;
+            Self { value, id: *ids - 1 }
+        }
+    }
+
+    /// Unwinds without running the panic hook, so the tests do not flood the output.
+    fn explode() -> ! {
+        std::panic::resume_unwind(Box::new("bomb"))
+    }
+
+    impl Ord for Bomb {
+        fn cmp(&self, other: &Self) -> Ordering {
+            FUSE.with(|fuse| match fuse.get() {
+                Some(0) => {
+                    fuse.set(None);
+                    explode()
+                }
+                Some(left) => fuse.set(Some(left - 1)),
+                None => {}
+            });
+            self.value.cmp(&other.value)
+        }
+    }
+
+    impl PartialOrd for Bomb {
+        fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+            Some(self.cmp(other))
+        }
+    }
+
+    impl PartialEq for Bomb {
+        fn eq(&self, other: &Self) -> bool {
+            self.cmp(other) == Ordering::Equal
+        }
+    }
+
+    impl Eq for Bomb {}
+
+    impl Drop for Bomb {
+        fn drop(&mut self) {
+            DROPS.with(|drops| drops.borrow_mut().push(self.id));
+            if DROP_FUSE.get() == Some(self.value) {
+                DROP_FUSE.set(None);
+                explode()
+            }
+        }
+    }
+
+    pub(crate) fn values<P: BalancePolicy>(set: &AVLTreeSet<Bomb, P>) -> Vec<u8> {
+        set.iter().map(|bomb| bomb.value).collect()
+    }
+
+    /// True if every one of the ids Bombs made so far was dropped exactly once.
+    pub(crate) fn dropped_once(ids: usize) -> bool {
+        let mut drops = DROPS.take();
+        drops.sort();
+        drops == (0..ids).collect::<Vec<_>>()
+    }
+
+    #[quickcheck]
+    fn panicking_ord_keeps_set_valid(ops: Vec<(u8, u8, u8, u8)>) -> bool {
+        DROPS.take();
+        let mut ids = 0;
+        let mut set = AVLTreeSet::new();
+        let mut model = BTreeSet::new();
+
+        let consistent = ops.into_iter().all(|(op, value, other, fuse)| {
+            let (value, other) = (value % 32, other % 32);
+            let before = model.clone();
+            let mut after = model.clone();
+            let mut removed = model.clone();
+            removed.remove(&value);
+
+            FUSE.set(Some(fuse % 16));
+            let result = std::panic::catch_unwind(AssertUnwindSafe(|| match op % 3 {
+                0 => {
+                    after.insert(value);
+                    set.insert(Bomb::new(value, &mut ids));
+                }
+                1 => {
+                    after.remove(&value);
+                    set.take(&Bomb::new(value, &mut ids));
+                }
+                _ => {
+                    if after.remove(&value) {
+                        after.insert(other);
+                    }
+                    set.get_mut_with(&Bomb::new(value, &mut ids), |bomb| bomb.value = other);
+                }
+            }));
+            FUSE.set(None);
+
+            // Unless the operation finished, the set is as it was, or for get_mut_with without the element
+            let contents = values(&set).into_iter().collect::<BTreeSet<_>>();
+            let allowed = contents == after
+                || (result.is_err() && (contents == before || (op % 3 == 2 && contents == removed)));
+            model = contents;
+
+            allowed && set.validate().is_ok()
+        });
+
+        drop(set);
+        consistent && dropped_once(ids)
+    }
+
+    #[test]
+    fn panicking_drop() {
+        DROPS.take();
+        let mut ids = 0;
+        let mut set = AVLTreeSet::new();
+        for value in 0..32 {
+            set.insert(Bomb::new(value, &mut ids));
+        }
+
+        // The rejected duplicate panics as it is dropped, after the set was left alone
+        DROP_FUSE.set(Some(7));
+        let duplicate = Bomb::new(7, &mut ids);
+        assert!(std::panic::catch_unwind(AssertUnwindSafe(|| set.insert(duplicate))).is_err());
+        assert_eq!(values(&set), (0..32).collect::<Vec<_>>());
+        assert!(set.validate().is_ok());
+
+        // One element panicking as the set is dropped does not stop the others being dropped
+        DROP_FUSE.set(Some(20));
+        assert!(std::panic::catch_unwind(AssertUnwindSafe(|| drop(set))).is_err());
+        assert!(dropped_once(ids));
+    }
+}
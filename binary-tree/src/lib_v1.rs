@@ -1,94 +1,698 @@
+/*
+    Red-Black Tree in rust.
+    A left-leaning red-black tree, where red links always lean left.
+    This keeps the tree equivalent to a 2-3 tree, which makes the fix-up after
+    insert and delete a handful of local rotations and colour flips.
+*/
 
-pub struct RedBlackTree<T> {
-    root: TreeNode<T>,
+use std::cmp::Ordering;
+use std::mem::replace;
+use std::ops::{Bound, RangeBounds};
+
+#[derive(Debug, PartialEq, Clone)]
+/// An ordered map based on a Red-Black Tree.
+pub struct RedBlackTree<K: Ord, V> {
+    root: Link<K, V>,
     size: usize,
 }
 
-impl <T> RedBlackTree<T>
-where
-    T: PartialEq + PartialOrd + Clone,
-{
-    pub fn new(data: T, key: usize) -> Self {
-        let root = TreeNode::new(data, key, Color::Black);
-        let size = 1;
-        Self {root, size}
+impl<K: Ord, V> Default for RedBlackTree<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl <K: Ord, V> RedBlackTree<K, V> {
+    /// Creates a new, empty RedBlackTree.
+    ///
+    /// Does not allocate anything on its own.
+    pub fn new() -> Self {
+        Self { root: None, size: 0 }
+    }
+
+    /// Returns the number of entries in the tree.
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    /// Returns true if the tree contains no entries.
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    /// Returns a reference to the data stored for key, if any.
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.search(key).map(|node| &node.data)
+    }
+
+    /// Returns true if the tree contains an entry for key.
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.search(key).is_some()
+    }
+
+    /// Inserts data for key.
+    ///
+    /// If the tree did not have an entry for key, None is returned.
+    ///
+    /// If the tree did have an entry for key, the data is updated and the old data is returned.
+    pub fn insert(&mut self, key: K, data: V) -> Option<V> {
+        let (mut root, old_data) = insert(self.root.take(), key, data);
+        root.color = Color::Black;
+        self.root = Some(root);
+
+        if old_data.is_none() {
+            self.size += 1;
+        }
+
+        old_data
+    }
+
+    /// Removes the entry for key and returns its data, if any.
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        self.remove_entry(key).map(|(_, data)| data)
+    }
+
+    /// Removes the entry for key and returns the stored key and data, if any.
+    pub fn remove_entry(&mut self, key: &K) -> Option<(K, V)> {
+        if !self.contains_key(key) {
+            return None;
+        }
+
+        let mut root = self.root.take().unwrap();
+
+        // If both children are black, make the root red so there is a red link to move down
+        if !is_red(&root.left) && !is_red(&root.right) {
+            root.color = Color::Red;
+        }
+
+        let (root, entry) = delete(root, key);
+        self.root = root;
+        if let Some(root) = self.root.as_mut() {
+            root.color = Color::Black;
+        }
+
+        self.size -= 1;
+
+        entry
     }
 
-    pub fn search(&mut self, key: usize) -> T {
-        match self.common_search(key) {
-            (_node , None) => _node.data,
-            _ => panic!("No node found for given key!")
+    /// Returns the entry with the smallest key, if any.
+    pub fn first_key_value(&self) -> Option<(&K, &V)> {
+        let mut current_node = self.root.as_ref()?;
+
+        while let Some(left) = current_node.left.as_ref() {
+            current_node = left;
         }
+
+        Some((&current_node.key, &current_node.data))
     }
 
-    pub fn insert(&mut self, data: T, key: usize) {
-        let (mut parent_node, dir) = match self.common_search(key) {
-            (_node, Some(_dir)) => (_node, _dir),
-            _ => panic!("Key already exists in tree. Does not accept duplicates!")
+    /// Returns the entry with the largest key, if any.
+    pub fn last_key_value(&self) -> Option<(&K, &V)> {
+        let mut current_node = self.root.as_ref()?;
+
+        while let Some(right) = current_node.right.as_ref() {
+            current_node = right;
+        }
+
+        Some((&current_node.key, &current_node.data))
+    }
+
+    /// Gets an iterator that visits the entries with keys in range in ascending key order.
+    pub fn range<R: RangeBounds<K>>(
+        &self,
+        range: R,
+    ) -> impl DoubleEndedIterator<Item = (&'_ K, &'_ V)> + ExactSizeIterator + '_ {
+        let before_start = |key: &K| match range.start_bound() {
+            Bound::Included(start) => key < start,
+            Bound::Excluded(start) => key <= start,
+            Bound::Unbounded => false,
         };
-        let mut node = TreeNode::new(data, key, Color::Red);
-        match dir {
-            Direction::Left => parent_node.left = Some(Box::new(node)),
-            Direction::Right => parent_node.right = Some(Box::new(node)),
-        }
-    }
-
-    fn common_search(&mut self, key: usize) -> (TreeNode<T>, Option<Direction>)  {
-        let mut node = self.root.clone();
-        let mut dir: Option<Direction> = None;
-        while node.key != key {
-            if node.key > key {
-                match node.left {
-                    Some(_node) => node = *_node,
-                    None => dir = Some(Direction::Left),
-                }
+        let before_end = |key: &K| match range.end_bound() {
+            Bound::Included(end) => key <= end,
+            Bound::Excluded(end) => key < end,
+            Bound::Unbounded => true,
+        };
+
+        // Path to the first key in range, holding every node still to visit
+        let mut front = Vec::new();
+        let mut current_tree = &self.root;
+        while let Some(current_node) = current_tree {
+            if before_start(&current_node.key) {
+                current_tree = &current_node.right;
+            } else {
+                front.push(&**current_node);
+                current_tree = &current_node.left;
+            }
+        }
+
+        // Path to the last key in range
+        let mut back = Vec::new();
+        let mut current_tree = &self.root;
+        while let Some(current_node) = current_tree {
+            if before_end(&current_node.key) {
+                back.push(&**current_node);
+                current_tree = &current_node.right;
             } else {
-                match node.right {
-                    Some(_node) => node = *_node,
-                    None => dir = Some(Direction::Right),
-                }
+                current_tree = &current_node.left;
             }
         }
-        (node, dir)
+
+        RedBlackTreeRange {
+            front,
+            back,
+            remaining: self.rank(before_end).saturating_sub(self.rank(before_start)),
+        }
+        .map(|_node| (&_node.key, &_node.data))
+    }
+
+    /// Gets an iterator that visits the entries in the tree in ascending key order.
+    pub fn iter(&self) -> impl Iterator<Item = (&'_ K, &'_ V)> + '_ {
+        self.node_iter().map(|_node| (&_node.key, &_node.data))
+    }
+
+    /// Gets an iterator that visits the keys in the tree in ascending order.
+    pub fn keys(&self) -> impl Iterator<Item = &'_ K> + '_ {
+        self.node_iter().map(|_node| &_node.key)
+    }
+
+    /// Gets an iterator that visits the data in the tree in ascending key order.
+    pub fn values(&self) -> impl Iterator<Item = &'_ V> + '_ {
+        self.node_iter().map(|_node| &_node.data)
+    }
+
+    /// Finds the node for key, if any.
+    fn search(&self, key: &K) -> Option<&TreeNode<K, V>> {
+        let mut current_tree = &self.root;
+
+        while let Some(current_node) = current_tree {
+            match current_node.key.cmp(key) {
+                Ordering::Greater => current_tree = &current_node.left,
+                Ordering::Equal => return Some(current_node),
+                Ordering::Less => current_tree = &current_node.right,
+            }
+        }
+        None
+    }
+
+    /// Number of keys for which before returns true.
+    ///
+    /// before must be true for every key up to some point and false after it.
+    fn rank(&self, before: impl Fn(&K) -> bool) -> usize {
+        let mut current_tree = &self.root;
+        let mut rank = 0;
+
+        while let Some(current_node) = current_tree {
+            if before(&current_node.key) {
+                rank += size(&current_node.left) + 1;
+                current_tree = &current_node.right;
+            } else {
+                current_tree = &current_node.left;
+            }
+        }
+
+        rank
+    }
+
+    /// An iterator over the nodes instead of the entries they contain
+    fn node_iter(&self) -> RedBlackTreeNodeIter<'_, K, V> {
+        RedBlackTreeNodeIter {
+            prev_nodes: Vec::default(),
+            current_tree: &self.root,
+        }
     }
 }
 
-#[derive(Clone)]
-struct TreeNode<T> {
+impl<K: Ord, V> FromIterator<(K, V)> for RedBlackTree<K, V> {
+    /// Create a RedBlackTree from an iterator of entries.
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut tree = Self::new();
+
+        for (key, data) in iter {
+            tree.insert(key, data);
+        }
+
+        tree
+    }
+}
+
+type Link<K, V> = Option<Box<TreeNode<K, V>>>;
+
+#[derive(Debug, PartialEq, Clone)]
+struct TreeNode<K, V> {
     color: Color,
-    data: T,
-    key: usize,
-    parent: Option<Box<TreeNode<T>>>,
-    left: Option<Box<TreeNode<T>>>,
-    right: Option<Box<TreeNode<T>>>,
+    key: K,
+    data: V,
+    /// Number of nodes in the subtree rooted here
+    size: usize,
+    left: Link<K, V>,
+    right: Link<K, V>,
 }
 
-struct NodePtr<T>(*mut TreeNode<T>);
+impl <K, V> TreeNode<K, V> {
+    fn new(key: K, data: V, color: Color) -> Self {
+        Self {color, key, data, size: 1, left: None, right: None}
+    }
+
+    /// Recompute the size of the subtree from its children
+    fn update_size(&mut self) {
+        self.size = 1 + size(&self.left) + size(&self.right);
+    }
 
-impl <T> TreeNode<T> {
-    fn new(data: T, key: usize, color: Color,) -> Self {
-        Self {color, data, key, parent: None, left: None, right: None}
+    /// Flip the colour of the node and both its children
+    fn flip_colors(&mut self) {
+        self.color = self.color.flip();
+        if let Some(left) = self.left.as_mut() {
+            left.color = left.color.flip();
+        }
+        if let Some(right) = self.right.as_mut() {
+            right.color = right.color.flip();
+        }
     }
 }
 
-#[derive(Clone)]
+#[derive(Debug, PartialEq, Clone, Copy)]
 enum Color {
     Black, Red,
 }
 
-enum Direction {
-    Right, Left,
+impl Color {
+    fn flip(self) -> Self {
+        match self {
+            Color::Black => Color::Red,
+            Color::Red => Color::Black,
+        }
+    }
+}
+
+/// True if the link points to a red node. Empty links are black.
+fn is_red<K, V>(link: &Link<K, V>) -> bool {
+    link.as_ref().is_some_and(|node| node.color == Color::Red)
+}
+
+/// Number of nodes in the subtree the link points to.
+fn size<K, V>(link: &Link<K, V>) -> usize {
+    link.as_ref().map_or(0, |node| node.size)
+}
+
+/// True if the left child of the link's node is red.
+fn is_left_red<K, V>(link: &Link<K, V>) -> bool {
+    link.as_ref().is_some_and(|node| is_red(&node.left))
+}
+
+/*
+        h                        x
+       / \     Left Rotate      / \
+      L   x       ———>         h   R
+         / \                  / \
+        M   R                L   M
+*/
+/// Rotate a right leaning red link to lean left
+fn rotate_left<K, V>(mut h: Box<TreeNode<K, V>>) -> Box<TreeNode<K, V>> {
+    let mut x = h.right.take().unwrap();
+    h.right = x.left.take();
+    x.color = h.color;
+    h.color = Color::Red;
+    h.update_size();
+    x.left = Some(h);
+    x.update_size();
+    x
+}
+
+/*
+          h                      x
+         / \   Right Rotate     / \
+        x   R     ———>         L   h
+       / \                        / \
+      L   M                      M   R
+*/
+/// Rotate a left leaning red link to lean right
+fn rotate_right<K, V>(mut h: Box<TreeNode<K, V>>) -> Box<TreeNode<K, V>> {
+    let mut x = h.left.take().unwrap();
+    h.left = x.right.take();
+    x.color = h.color;
+    h.color = Color::Red;
+    h.update_size();
+    x.right = Some(h);
+    x.update_size();
+    x
+}
+
+/// Restore the left leaning invariants on the way back up from an insert or delete
+fn fix_up<K, V>(mut h: Box<TreeNode<K, V>>) -> Box<TreeNode<K, V>> {
+    if is_red(&h.right) && !is_red(&h.left) {
+        h = rotate_left(h);
+    }
+    if is_red(&h.left) && is_left_red(&h.left) {
+        h = rotate_right(h);
+    }
+    if is_red(&h.left) && is_red(&h.right) {
+        h.flip_colors();
+    }
+    h.update_size();
+    h
+}
+
+/// Insert into a subtree. Returns the new subtree and the replaced data, if any.
+fn insert<K: Ord, V>(link: Link<K, V>, key: K, data: V) -> (Box<TreeNode<K, V>>, Option<V>) {
+    let mut h = match link {
+        Some(h) => h,
+        None => return (Box::new(TreeNode::new(key, data, Color::Red)), None),
+    };
+
+    let old_data = match key.cmp(&h.key) {
+        Ordering::Less => {
+            let (left, old_data) = insert(h.left.take(), key, data);
+            h.left = Some(left);
+            old_data
+        }
+        Ordering::Equal => Some(replace(&mut h.data, data)),
+        Ordering::Greater => {
+            let (right, old_data) = insert(h.right.take(), key, data);
+            h.right = Some(right);
+            old_data
+        }
+    };
+
+    (fix_up(h), old_data)
+}
+
+/// Make the left child of h or one of its children red, so we can delete from the left
+fn move_red_left<K, V>(mut h: Box<TreeNode<K, V>>) -> Box<TreeNode<K, V>> {
+    h.flip_colors();
+    if is_left_red(&h.right) {
+        h.right = Some(rotate_right(h.right.take().unwrap()));
+        h = rotate_left(h);
+        h.flip_colors();
+    }
+    h
+}
+
+/// Make the right child of h or one of its children red, so we can delete from the right
+fn move_red_right<K, V>(mut h: Box<TreeNode<K, V>>) -> Box<TreeNode<K, V>> {
+    h.flip_colors();
+    if is_left_red(&h.left) {
+        h = rotate_right(h);
+        h.flip_colors();
+    }
+    h
+}
+
+/// Delete the smallest node of a subtree. Returns the new subtree and the deleted node.
+fn delete_min<K, V>(mut h: Box<TreeNode<K, V>>) -> (Link<K, V>, Box<TreeNode<K, V>>) {
+    if h.left.is_none() {
+        // Left leaning, so a node without a left child has no right child either
+        return (None, h);
+    }
+
+    if !is_red(&h.left) && !is_left_red(&h.left) {
+        h = move_red_left(h);
+    }
+
+    let (left, min) = delete_min(h.left.take().unwrap());
+    h.left = left;
+
+    (Some(fix_up(h)), min)
+}
+
+/// Delete key from a subtree that contains it. Returns the new subtree and the deleted entry.
+fn delete<K: Ord, V>(mut h: Box<TreeNode<K, V>>, key: &K) -> (Link<K, V>, Option<(K, V)>) {
+    let entry = if key < &h.key {
+        if !is_red(&h.left) && !is_left_red(&h.left) {
+            h = move_red_left(h);
+        }
+
+        let (left, entry) = delete(h.left.take().unwrap(), key);
+        h.left = left;
+        entry
+    } else {
+        if is_red(&h.left) {
+            h = rotate_right(h);
+        }
+
+        if key == &h.key && h.right.is_none() {
+            return (None, Some((h.key, h.data)));
+        }
+
+        if !is_red(&h.right) && !is_left_red(&h.right) {
+            h = move_red_right(h);
+        }
+
+        if key == &h.key {
+            // Replace the entry with the smallest entry of the right side
+            let (right, min) = delete_min(h.right.take().unwrap());
+            h.right = right;
+            let key = replace(&mut h.key, min.key);
+            Some((key, replace(&mut h.data, min.data)))
+        } else {
+            let (right, entry) = delete(h.right.take().unwrap(), key);
+            h.right = right;
+            entry
+        }
+    };
+
+    (Some(fix_up(h)), entry)
+}
+
+#[derive(Debug)]
+struct RedBlackTreeNodeIter<'a, K, V> {
+    prev_nodes: Vec<&'a TreeNode<K, V>>,
+    current_tree: &'a Link<K, V>,
+}
+
+impl<'a, K: 'a, V: 'a> Iterator for RedBlackTreeNodeIter<'a, K, V> {
+    type Item = &'a TreeNode<K, V>;
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(current_node) = self.current_tree {
+            self.prev_nodes.push(current_node);
+            self.current_tree = &current_node.left;
+        }
+
+        let node = self.prev_nodes.pop()?;
+        self.current_tree = &node.right;
+
+        Some(node)
+    }
+}
+
+/// Node iterator over a range, walking in from both ends
+struct RedBlackTreeRange<'a, K, V> {
+    front: Vec<&'a TreeNode<K, V>>,
+    back: Vec<&'a TreeNode<K, V>>,
+    remaining: usize,
+}
+
+impl<'a, K: 'a, V: 'a> Iterator for RedBlackTreeRange<'a, K, V> {
+    type Item = &'a TreeNode<K, V>;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        let node = self.front.pop()?;
+        self.remaining -= 1;
+
+        // Next up is the leftmost node of the right side
+        let mut current_tree = &node.right;
+        while let Some(current_node) = current_tree {
+            self.front.push(current_node);
+            current_tree = &current_node.left;
+        }
+
+        Some(node)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
 }
 
+impl<'a, K: 'a, V: 'a> DoubleEndedIterator for RedBlackTreeRange<'a, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        let node = self.back.pop()?;
+        self.remaining -= 1;
+
+        // Next up from the back is the rightmost node of the left side
+        let mut current_tree = &node.left;
+        while let Some(current_node) = current_tree {
+            self.back.push(current_node);
+            current_tree = &current_node.right;
+        }
+
+        Some(node)
+    }
+}
+
+impl<'a, K: 'a, V: 'a> ExactSizeIterator for RedBlackTreeRange<'a, K, V> {}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    
+    use quickcheck::{Arbitrary, Gen};
+    use std::collections::BTreeMap;
+
+    impl<K: Arbitrary + Ord, V: Arbitrary> Arbitrary for RedBlackTree<K, V> {
+        fn arbitrary(g: &mut Gen) -> Self {
+            let vec: Vec<(K, V)> = Arbitrary::arbitrary(g);
+            vec.into_iter().collect()
+        }
+
+        fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+            let vec: Vec<(K, V)> = self.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+            Box::new(vec.shrink().map(|v| v.into_iter().collect::<Self>()))
+        }
+    }
+
+    /// Black height of a subtree if it is a valid left leaning red-black tree
+    fn black_height<K: Ord, V>(link: &Link<K, V>, lower: Option<&K>, upper: Option<&K>) -> Option<usize> {
+        let node = match link {
+            Some(node) => node,
+            None => return Some(1),
+        };
+
+        let ordered = lower.is_none_or(|lower| lower < &node.key)
+            && upper.is_none_or(|upper| &node.key < upper);
+        let leans_left = !is_red(&node.right);
+        let sized = node.size == 1 + size(&node.left) + size(&node.right);
+        let no_double_red = !(node.color == Color::Red && is_red(&node.left));
+        if !ordered || !leans_left || !no_double_red || !sized {
+            return None;
+        }
+
+        let left = black_height(&node.left, lower, Some(&node.key))?;
+        let right = black_height(&node.right, Some(&node.key), upper)?;
+        if left != right {
+            return None;
+        }
+
+        Some(left + (node.color == Color::Black) as usize)
+    }
+
+    fn is_red_black<K: Ord, V>(tree: &RedBlackTree<K, V>) -> bool {
+        !is_red(&tree.root)
+            && black_height(&tree.root, None, None).is_some()
+            && tree.node_iter().count() == tree.len()
+    }
+
+    #[quickcheck]
+    fn insert_red_black(tree: RedBlackTree<u16, u8>) -> bool {
+        is_red_black(&tree)
+    }
+
+    #[quickcheck]
+    fn remove_red_black(xs: Vec<(i16, u8)>) -> bool {
+        let negatives = xs.iter().map(|&(k, _)| k).filter(|&k| k < 0).collect::<Vec<_>>();
+        let mut tree = xs.into_iter().collect::<RedBlackTree<_, _>>();
+
+        for negative in negatives {
+            tree.remove(&negative);
+        }
+
+        is_red_black(&tree)
+    }
+
+    #[quickcheck]
+    fn remove_iterator_parity(xs: Vec<(i16, u8)>) -> bool {
+        let fives = xs.iter().map(|&(k, _)| k).filter(|k| k % 5 == 0).collect::<Vec<_>>();
+        let mut tree = xs.iter().cloned().collect::<RedBlackTree<_, _>>();
+        let mut btree_map = xs.iter().cloned().collect::<BTreeMap<_, _>>();
+
+        for five in fives {
+            assert_eq!(tree.remove(&five), btree_map.remove(&five));
+        }
+
+        tree.iter().eq(btree_map.iter())
+    }
+
+    #[quickcheck]
+    fn remove_parity(xs: Vec<(usize, u8)>) -> bool {
+        let odds = xs.iter().cloned().filter(|(k, _)| k % 2 == 1).collect::<Vec<_>>();
+        let mut tree = odds.iter().cloned().collect::<RedBlackTree<_, _>>();
+        let mut btree_map = odds.iter().cloned().collect::<BTreeMap<_, _>>();
+
+        xs.iter().all(|(k, _)| tree.remove(k) == btree_map.remove(k))
+    }
+
+    #[quickcheck]
+    fn get_parity(xs: Vec<(isize, u8)>, ys: Vec<isize>) -> bool {
+        let tree = xs.iter().cloned().collect::<RedBlackTree<_, _>>();
+        let btree_map = xs.iter().cloned().collect::<BTreeMap<_, _>>();
+
+        ys.iter().chain(xs.iter().map(|(k, _)| k)).all(|k| {
+            tree.get(k) == btree_map.get(k) && tree.contains_key(k) == btree_map.contains_key(k)
+        })
+    }
+
+    #[quickcheck]
+    fn iterator_parity(xs: Vec<(usize, u8)>) -> bool {
+        let tree = xs.iter().cloned().collect::<RedBlackTree<_, _>>();
+        let btree_map = xs.iter().cloned().collect::<BTreeMap<_, _>>();
+
+        tree.iter().eq(btree_map.iter())
+            && tree.keys().eq(btree_map.keys())
+            && tree.values().eq(btree_map.values())
+            && tree.len() == btree_map.len()
+    }
+
+    #[quickcheck]
+    fn insert_parity(mut btree_map: BTreeMap<u8, u8>, key: u8, data: u8) -> bool {
+        let mut tree = btree_map.iter().map(|(&k, &v)| (k, v)).collect::<RedBlackTree<_, _>>();
+        tree.insert(key, data) == btree_map.insert(key, data)
+    }
+
+    #[quickcheck]
+    fn range_parity(xs: Vec<(i16, u8)>, a: i16, b: i16) -> bool {
+        let tree = xs.iter().cloned().collect::<RedBlackTree<_, _>>();
+        let btree_map = xs.iter().cloned().collect::<BTreeMap<_, _>>();
+        let (low, high) = (a.min(b), a.max(b));
+        let excluded = (Bound::Excluded(low), Bound::Included(high));
+
+        tree.range(low..high).eq(btree_map.range(low..high))
+            && tree.range(low..=high).eq(btree_map.range(low..=high))
+            && tree.range(..high).eq(btree_map.range(..high))
+            && tree.range(excluded).eq(btree_map.range(excluded))
+    }
+
+    #[quickcheck]
+    fn range_avl_parity(xs: Vec<(i16, u8)>, a: i16, b: i16, steps: Vec<bool>) -> bool {
+        let tree = xs.iter().cloned().collect::<RedBlackTree<_, _>>();
+        let set = xs.iter().map(|&(k, _)| k).collect::<crate::AVLTreeSet<_>>();
+        let (low, high) = (a.min(b), a.max(b));
+
+        let mut keys = tree.range(low..=high).map(|(k, _)| k);
+        let mut model = set.range(low..=high);
+
+        // Walk in from both ends, checking the exact length after every step
+        steps.into_iter().all(|back| {
+            let same = if back {
+                keys.next_back() == model.next_back()
+            } else {
+                keys.next() == model.next()
+            };
+            same && keys.len() == model.len()
+        }) && keys.eq(model)
+            && tree.range(low..high).rev().map(|(k, _)| k).eq(set.range(low..high).rev())
+            && tree.range(high..low).len() == set.range(high..low).len()
+    }
+
+    #[quickcheck]
+    fn first_last_parity(xs: Vec<(isize, u8)>) -> bool {
+        let tree = xs.iter().cloned().collect::<RedBlackTree<_, _>>();
+        let btree_map = xs.iter().cloned().collect::<BTreeMap<_, _>>();
+
+        tree.first_key_value() == btree_map.first_key_value()
+            && tree.last_key_value() == btree_map.last_key_value()
+    }
+
     #[test]
     fn test() {
-        let t = RedBlackTree::<String>::new(format!("10"), 10);
-        let t_val = t.root.data;
-        assert_eq!(t_val, format!("10"))
+        let mut t = RedBlackTree::<usize, String>::new();
+        t.insert(10, "10".to_string());
+        assert_eq!(t.get(&10), Some(&"10".to_string()));
+        assert_eq!(t.get(&11), None);
     }
 }
-
@@ -0,0 +1,370 @@
+/*
+    Ordered map based on the AVL Tree.
+    Entries are stored in an AVLTreeSet ordered by key alone, so the set
+    does all of the balancing. Values can be changed in place, as they take
+    no part in the order.
+*/
+
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::mem::replace;
+use std::ops::RangeBounds;
+
+use crate::{range_predicates, AVLNode, AVLTree, AVLTreeSet};
+
+#[derive(Debug, Clone)]
+/// A key and its value, compared by key only.
+struct Entry<K, V> {
+    key: K,
+    value: V,
+}
+
+impl<K: Ord, V> PartialEq for Entry<K, V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key
+    }
+}
+
+impl<K: Ord, V> Eq for Entry<K, V> {}
+
+impl<K: Ord, V> PartialOrd for Entry<K, V> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<K: Ord, V> Ord for Entry<K, V> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key.cmp(&other.key)
+    }
+}
+
+type EntryTree<K, V> = AVLTree<Entry<K, V>>;
+
+/// An entry still to visit, with the subtree that follows it.
+type Pending<'a, K, V> = (&'a mut Entry<K, V>, &'a mut EntryTree<K, V>);
+
+#[derive(Debug, Clone)]
+/// An ordered map based on a AVL Tree.
+pub struct AVLTreeMap<K: Ord, V> {
+    set: AVLTreeSet<Entry<K, V>>,
+}
+
+impl<K: Ord, V> Default for AVLTreeMap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Ord, V: PartialEq> PartialEq for AVLTreeMap<K, V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.iter().eq(other.iter())
+    }
+}
+
+impl<K: Ord, V> AVLTreeMap<K, V> {
+    /// Creates a new, empty AVLTreeMap.
+    ///
+    /// Does not allocate anything on its own.
+    pub fn new() -> Self {
+        Self { set: AVLTreeSet::new() }
+    }
+
+    /// Returns the number of entries in the map.
+    pub fn len(&self) -> usize {
+        self.set.len()
+    }
+
+    /// Returns true if the map contains no entries.
+    pub fn is_empty(&self) -> bool {
+        self.set.is_empty()
+    }
+
+    /// Inserts a value for key. Returns the old value if the key was already present.
+    ///
+    /// The key itself is not updated.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        if let Some(old) = self.get_mut(&key) {
+            return Some(replace(old, value));
+        }
+
+        self.set.insert(Entry { key, value });
+        None
+    }
+
+    /// Returns a reference to the value for key, if any.
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let mut current_tree = &self.set.root;
+
+        while let Some(current_node) = current_tree {
+            match current_node.value.key.borrow().cmp(key) {
+                Ordering::Greater => current_tree = &current_node.left,
+                Ordering::Equal => return Some(&current_node.value.value),
+                Ordering::Less => current_tree = &current_node.right,
+            }
+        }
+        None
+    }
+
+    /// Returns a mutable reference to the value for key, if any.
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let mut current_tree = &mut self.set.root;
+
+        while let Some(current_node) = current_tree {
+            match current_node.value.key.borrow().cmp(key) {
+                Ordering::Greater => current_tree = &mut current_node.left,
+                Ordering::Equal => return Some(&mut current_node.value.value),
+                Ordering::Less => current_tree = &mut current_node.right,
+            }
+        }
+        None
+    }
+
+    /// Returns true if the map contains an entry for key.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.get(key).is_some()
+    }
+
+    /// Removes the entry for key and returns its value, if any.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.remove_entry(key).map(|(_, value)| value)
+    }
+
+    /// Removes the entry for key and returns it, if any.
+    pub fn remove_entry<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.set
+            .take_by(|entry| entry.key.borrow().cmp(key))
+            .map(|entry| (entry.key, entry.value))
+    }
+
+    /// Returns the entry with the smallest key, if any.
+    pub fn first_key_value(&self) -> Option<(&K, &V)> {
+        self.set.first().map(|entry| (&entry.key, &entry.value))
+    }
+
+    /// Returns the entry with the largest key, if any.
+    pub fn last_key_value(&self) -> Option<(&K, &V)> {
+        self.set.last().map(|entry| (&entry.key, &entry.value))
+    }
+
+    /// Gets a double ended iterator over the entries with keys in range, in ascending key order.
+    pub fn range<Q, R>(&self, range: R) -> impl DoubleEndedIterator<Item = (&'_ K, &'_ V)> + '_
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+        R: RangeBounds<Q>,
+    {
+        let (before_start, before_end) = range_predicates(&range);
+
+        self.set
+            .range_by(|entry| before_start(&entry.key), |entry| before_end(&entry.key))
+            .map(|entry| (&entry.key, &entry.value))
+    }
+
+    /// Gets an iterator over the entries with keys in range, with mutable values.
+    pub fn range_mut<Q, R>(&mut self, range: R) -> AVLTreeMapIterMut<'_, K, V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+        R: RangeBounds<Q>,
+    {
+        let (before_start, before_end) = range_predicates(&range);
+        let start = self.set.rank(|entry| before_start(&entry.key));
+        let end = self.set.rank(|entry| before_end(&entry.key)).max(start);
+
+        AVLTreeMapIterMut::new(&mut self.set.root, start, end - start)
+    }
+
+    /// Gets an iterator that visits the entries in ascending key order.
+    pub fn iter(&self) -> impl Iterator<Item = (&'_ K, &'_ V)> + '_ {
+        self.set.iter().map(|entry| (&entry.key, &entry.value))
+    }
+
+    /// Gets an iterator that visits the entries in ascending key order, with mutable values.
+    pub fn iter_mut(&mut self) -> AVLTreeMapIterMut<'_, K, V> {
+        let len = self.len();
+        AVLTreeMapIterMut::new(&mut self.set.root, 0, len)
+    }
+
+    /// Gets an iterator that visits the keys in ascending order.
+    pub fn keys(&self) -> impl Iterator<Item = &'_ K> + '_ {
+        self.set.iter().map(|entry| &entry.key)
+    }
+
+    /// Gets an iterator that visits the values in ascending key order.
+    pub fn values(&self) -> impl Iterator<Item = &'_ V> + '_ {
+        self.set.iter().map(|entry| &entry.value)
+    }
+
+    /// Gets an iterator that visits mutable values in ascending key order.
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &'_ mut V> + '_ {
+        self.iter_mut().map(|(_, value)| value)
+    }
+}
+
+impl<K: Ord, V> FromIterator<(K, V)> for AVLTreeMap<K, V> {
+    /// Create an AVLTreeMap from an iterator. Later values replace earlier ones.
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut map = Self::new();
+
+        for (key, value) in iter {
+            map.insert(key, value);
+        }
+
+        map
+    }
+}
+
+#[derive(Debug)]
+/// An iterator over the entries of an AVLTreeMap, with mutable values.
+pub struct AVLTreeMapIterMut<'a, K, V> {
+    prev_nodes: Vec<Pending<'a, K, V>>,
+    remaining: usize,
+}
+
+impl<'a, K, V> AVLTreeMapIterMut<'a, K, V> {
+    /// Iterator over len entries starting at position start.
+    fn new(tree: &'a mut EntryTree<K, V>, start: usize, len: usize) -> Self {
+        let mut iter = Self {
+            prev_nodes: Vec::new(),
+            remaining: len,
+        };
+        if len > 0 {
+            iter.seek(tree, start);
+        }
+        iter
+    }
+
+    /// Pushes the path to the entry at position in tree.
+    fn seek(&mut self, tree: &'a mut EntryTree<K, V>, mut position: usize) {
+        let mut current_node = tree.as_deref_mut();
+
+        while let Some(node) = current_node {
+            let AVLNode { value, left, right, .. } = node;
+            let left_size = left.as_ref().map_or(0, |node| node.size);
+
+            if position <= left_size {
+                self.prev_nodes.push((value, right));
+                if position == left_size {
+                    break;
+                }
+                current_node = left.as_deref_mut();
+            } else {
+                position -= left_size + 1;
+                current_node = right.as_deref_mut();
+            }
+        }
+    }
+}
+
+impl<'a, K, V> Iterator for AVLTreeMapIterMut<'a, K, V> {
+    type Item = (&'a K, &'a mut V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        let (entry, right) = self.prev_nodes.pop()?;
+        self.remaining -= 1;
+        if self.remaining > 0 {
+            self.seek(right, 0);
+        }
+
+        Some((&entry.key, &mut entry.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<K, V> ExactSizeIterator for AVLTreeMapIterMut<'_, K, V> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn build(xs: &[(i16, u8)]) -> (AVLTreeMap<i16, u32>, BTreeMap<i16, u32>) {
+        let map = xs.iter().map(|&(k, v)| (k, v as u32)).collect::<AVLTreeMap<_, _>>();
+        let btree_map = xs.iter().map(|&(k, v)| (k, v as u32)).collect::<BTreeMap<_, _>>();
+        (map, btree_map)
+    }
+
+    #[quickcheck]
+    fn map_parity(xs: Vec<(i16, u8)>, ys: Vec<i16>) -> bool {
+        let (mut map, mut btree_map) = build(&xs);
+
+        let queries = ys.iter().all(|y| map.get(y) == btree_map.get(y))
+            && map.len() == btree_map.len()
+            && map.iter().eq(btree_map.iter());
+        let removes = ys.iter().all(|y| map.remove_entry(y) == btree_map.remove_entry(y));
+
+        queries && removes && map.set.validate().is_ok() && map.iter().eq(btree_map.iter())
+    }
+
+    #[quickcheck]
+    fn iter_mut_parity(xs: Vec<(i16, u8)>) -> bool {
+        let (mut map, mut btree_map) = build(&xs);
+
+        for ((key, value), (btree_key, btree_value)) in map.iter_mut().zip(btree_map.iter_mut()) {
+            assert_eq!(key, btree_key);
+            *value += key.unsigned_abs() as u32;
+            *btree_value += btree_key.unsigned_abs() as u32;
+        }
+        map.values_mut().for_each(|value| *value *= 3);
+        btree_map.values_mut().for_each(|value| *value *= 3);
+
+        map.iter_mut().len() == btree_map.len() && map.iter().eq(btree_map.iter())
+    }
+
+    #[quickcheck]
+    fn range_mut_parity(xs: Vec<(i16, u8)>, a: i16, b: i16) -> bool {
+        let (mut map, mut btree_map) = build(&xs);
+        let (low, high) = (a.min(b), a.max(b));
+
+        map.range_mut(low..high).for_each(|(_, value)| *value += 1000);
+        btree_map.range_mut(low..high).for_each(|(_, value)| *value += 1000);
+        map.range_mut(..=low).for_each(|(_, value)| *value += 7);
+        btree_map.range_mut(..=low).for_each(|(_, value)| *value += 7);
+
+        map.range(low..=high).eq(btree_map.range(low..=high))
+            && map.range_mut(low..high).len() == btree_map.range(low..high).count()
+            && map.iter().eq(btree_map.iter())
+    }
+
+    #[test]
+    fn get_mut_and_borrowed_keys() {
+        let mut map = AVLTreeMap::new();
+        map.insert("b".to_string(), 1);
+        map.insert("a".to_string(), 2);
+
+        *map.get_mut("a").unwrap() += 10;
+        assert_eq!(map.get("a"), Some(&12));
+        assert_eq!(map.insert("b".to_string(), 5), Some(1));
+        assert_eq!(map.get_mut("c"), None);
+        assert_eq!(map.remove("a"), Some(12));
+        assert_eq!(map.keys().collect::<Vec<_>>(), ["b"]);
+    }
+}
@@ -0,0 +1,269 @@
+/*
+    K-way merge over AVLTreeSets.
+    A range iterator per set walks it in order, and a min-heap holds the
+    next element of each set tagged with the set's index, so each step
+    costs O(log k) for k sets. Seeking pops only the heads below the bound
+    and moves their iterators on from where they are.
+*/
+
+use std::borrow::Borrow;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+use crate::{AVLTreeSet, AVLTreeSetRange, AvlPolicy, BalancePolicy};
+
+/// Merges sets into their sorted union, each distinct element once.
+///
+/// Of equal elements, the one from the set earliest in sets is returned.
+/// Call `keep_duplicates` to get every element tagged with its set instead.
+pub fn merge_iter<'a, T: Ord, P: BalancePolicy>(sets: &[&'a AVLTreeSet<T, P>]) -> MergeIter<'a, T, P> {
+    let mut cursors = sets.iter().map(|set| set.range::<T, _>(..)).collect::<Vec<_>>();
+    let heads = cursors
+        .iter_mut()
+        .enumerate()
+        .filter_map(|(source, cursor)| cursor.next().map(|value| Reverse((value, source))))
+        .collect();
+
+    MergeIter {
+        merge: Merge { cursors, heads },
+    }
+}
+
+/// The next element of every set, and the iterators over the rest.
+struct Merge<'a, T, P: BalancePolicy> {
+    cursors: Vec<AVLTreeSetRange<'a, T, P>>,
+    /// Smallest element first, ties broken by set index
+    heads: BinaryHeap<Reverse<(&'a T, usize)>>,
+}
+
+impl<'a, T: Ord, P: BalancePolicy> Merge<'a, T, P> {
+    fn pop(&mut self) -> Option<(usize, &'a T)> {
+        let Reverse((value, source)) = self.heads.pop()?;
+        if let Some(next) = self.cursors[source].next() {
+            self.heads.push(Reverse((next, source)));
+        }
+
+        Some((source, value))
+    }
+
+    fn peek(&self) -> Option<&'a T> {
+        self.heads.peek().map(|&Reverse((value, _))| value)
+    }
+
+    fn seek<Q>(&mut self, bound: &Q)
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        while let Some(&Reverse((value, source))) = self.heads.peek() {
+            if value.borrow() >= bound {
+                break;
+            }
+            self.heads.pop();
+
+            let cursor = &mut self.cursors[source];
+            cursor.seek(bound);
+            if let Some(next) = cursor.next() {
+                self.heads.push(Reverse((next, source)));
+            }
+        }
+    }
+
+    /// Number of elements left in every set together.
+    fn remaining(&self) -> usize {
+        self.heads.len() + self.cursors.iter().map(ExactSizeIterator::len).sum::<usize>()
+    }
+}
+
+/// An iterator over the union of several AVLTreeSets, in ascending order.
+pub struct MergeIter<'a, T, P: BalancePolicy = AvlPolicy> {
+    merge: Merge<'a, T, P>,
+}
+
+impl<'a, T: Ord, P: BalancePolicy> MergeIter<'a, T, P> {
+    /// Yields every element of every set instead, with the index of its set.
+    ///
+    /// Equal elements come in the order of their sets.
+    pub fn keep_duplicates(self) -> TaggedMergeIter<'a, T, P> {
+        TaggedMergeIter { merge: self.merge }
+    }
+
+    /// Skips ahead to the first element not less than bound, moving each set's iterator
+    /// on from its current position.
+    pub fn seek<Q>(&mut self, bound: &Q)
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.merge.seek(bound);
+    }
+}
+
+impl<'a, T: Ord, P: BalancePolicy> Iterator for MergeIter<'a, T, P> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        let (_, value) = self.merge.pop()?;
+        while self.merge.peek() == Some(value) {
+            self.merge.pop();
+        }
+
+        Some(value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        // No set has duplicates of its own, so there are at least as many as left in any one set
+        let largest = self
+            .merge
+            .heads
+            .iter()
+            .map(|&Reverse((_, source))| 1 + self.merge.cursors[source].len())
+            .max()
+            .unwrap_or(0);
+
+        (largest, Some(self.merge.remaining()))
+    }
+}
+
+/// An iterator over every element of several AVLTreeSets with the index of its set, in ascending order.
+pub struct TaggedMergeIter<'a, T, P: BalancePolicy = AvlPolicy> {
+    merge: Merge<'a, T, P>,
+}
+
+impl<T: Ord, P: BalancePolicy> TaggedMergeIter<'_, T, P> {
+    /// Skips ahead to the first element not less than bound, moving each set's iterator
+    /// on from its current position.
+    pub fn seek<Q>(&mut self, bound: &Q)
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.merge.seek(bound);
+    }
+}
+
+impl<'a, T: Ord, P: BalancePolicy> Iterator for TaggedMergeIter<'a, T, P> {
+    type Item = (usize, &'a T);
+
+    fn next(&mut self) -> Option<(usize, &'a T)> {
+        self.merge.pop()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.merge.remaining();
+        (remaining, Some(remaining))
+    }
+}
+
+impl<T: Ord, P: BalancePolicy> ExactSizeIterator for TaggedMergeIter<'_, T, P> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{TreapPolicy, WeightBalancedPolicy};
+    use std::collections::BTreeSet;
+
+    fn shards<P: BalancePolicy + Default>(xss: &[Vec<u8>]) -> Vec<AVLTreeSet<u8, P>> {
+        xss.iter().map(|xs| xs.iter().cloned().collect()).collect()
+    }
+
+    /// Every element of every shard with its shard, in merge order.
+    fn tagged_model(xss: &[Vec<u8>]) -> Vec<(usize, u8)> {
+        let mut tagged = xss
+            .iter()
+            .enumerate()
+            .flat_map(|(source, xs)| xs.iter().collect::<BTreeSet<_>>().into_iter().map(move |&x| (x, source)))
+            .collect::<Vec<_>>();
+        tagged.sort();
+        tagged.into_iter().map(|(x, source)| (source, x)).collect()
+    }
+
+    fn merge<P: BalancePolicy + Default>(xss: Vec<Vec<u8>>) -> bool {
+        let sets = shards::<P>(&xss);
+        let refs = sets.iter().collect::<Vec<_>>();
+        let union = xss.iter().flatten().collect::<BTreeSet<_>>();
+
+        let merged = merge_iter(&refs);
+        let (low, high) = merged.size_hint();
+
+        low <= union.len()
+            && high.is_some_and(|high| high >= union.len())
+            && merged.eq(union)
+            && merge_iter(&refs)
+                .keep_duplicates()
+                .map(|(source, &x)| (source, x))
+                .eq(tagged_model(&xss))
+            && merge_iter(&refs).keep_duplicates().len() == tagged_model(&xss).len()
+    }
+
+    #[quickcheck]
+    fn merge_parity(xss: Vec<Vec<u8>>) -> bool {
+        merge::<AvlPolicy>(xss)
+    }
+
+    #[quickcheck]
+    fn weight_balanced_merge_parity(xss: Vec<Vec<u8>>) -> bool {
+        merge::<WeightBalancedPolicy>(xss)
+    }
+
+    #[quickcheck]
+    fn treap_merge_parity(xss: Vec<Vec<u8>>) -> bool {
+        merge::<TreapPolicy>(xss)
+    }
+
+    fn seek<P: BalancePolicy + Default>(xss: Vec<Vec<u8>>, steps: Vec<(bool, u8)>) -> bool {
+        let sets = shards::<P>(&xss);
+        let refs = sets.iter().collect::<Vec<_>>();
+        let mut merged = merge_iter(&refs);
+        let mut tagged = merge_iter(&refs).keep_duplicates();
+        let mut union = xss.iter().flatten().cloned().collect::<BTreeSet<_>>().into_iter().peekable();
+        let mut tagged_model = tagged_model(&xss).into_iter().peekable();
+
+        steps.into_iter().all(|(seek, bound)| {
+            if seek {
+                merged.seek(&bound);
+                tagged.seek(&bound);
+                while union.next_if(|&x| x < bound).is_some() {}
+                while tagged_model.next_if(|&(_, x)| x < bound).is_some() {}
+                tagged.len() == tagged_model.len()
+            } else {
+                merged.next().copied() == union.next()
+                    && tagged.next().map(|(source, &x)| (source, x)) == tagged_model.next()
+            }
+        }) && merged.copied().eq(union)
+            && tagged.map(|(source, &x)| (source, x)).eq(tagged_model)
+    }
+
+    #[quickcheck]
+    fn seek_parity(xss: Vec<Vec<u8>>, steps: Vec<(bool, u8)>) -> bool {
+        seek::<AvlPolicy>(xss, steps)
+    }
+
+    #[quickcheck]
+    fn weight_balanced_seek_parity(xss: Vec<Vec<u8>>, steps: Vec<(bool, u8)>) -> bool {
+        seek::<WeightBalancedPolicy>(xss, steps)
+    }
+
+    #[quickcheck]
+    fn treap_seek_parity(xss: Vec<Vec<u8>>, steps: Vec<(bool, u8)>) -> bool {
+        seek::<TreapPolicy>(xss, steps)
+    }
+
+    #[test]
+    fn union_of_shards() {
+        let a = [1, 4, 7].into_iter().collect::<AVLTreeSet<_>>();
+        let b = [2, 4, 8].into_iter().collect::<AVLTreeSet<_>>();
+        let c = AVLTreeSet::new();
+
+        assert_eq!(merge_iter(&[&a, &b, &c]).collect::<Vec<_>>(), [&1, &2, &4, &7, &8]);
+        assert_eq!(
+            merge_iter(&[&a, &b]).keep_duplicates().collect::<Vec<_>>(),
+            [(0, &1), (1, &2), (0, &4), (1, &4), (0, &7), (1, &8)]
+        );
+
+        let mut merged = merge_iter(&[&a, &b]);
+        merged.seek(&5);
+        assert_eq!(merged.collect::<Vec<_>>(), [&7, &8]);
+        assert_eq!(merge_iter::<u8, AvlPolicy>(&[]).next(), None);
+    }
+}
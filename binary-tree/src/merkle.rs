@@ -0,0 +1,365 @@
+/*
+    Merkle-hashed AVL set.
+    Every node caches a hash of the elements in its subtree, taken in order:
+    the polynomial hash h(e1) X^(n-1) + h(e2) X^(n-2) + ... + h(en).
+    Two such hashes combine without looking inside them, so the hash depends
+    only on the contents and never on the shape of the tree, and the hash of
+    any range of elements can be assembled from O(log n) cached ones.
+*/
+
+use std::cmp::Ordering;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::ops::Bound;
+
+use crate::ops::{build, join, join2, split_at, Augment};
+use crate::{AVLNode, AVLTree, AVLTreeSetNodeIter};
+
+/// Base of the polynomial hash. Odd, so its powers never become zero.
+const BASE: u64 = 0x9e37_79b9_7f4a_7c15;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+/// Hash of a run of elements, with BASE to the power of its length.
+struct MerkleSummary {
+    hash: u64,
+    power: u64,
+}
+
+impl MerkleSummary {
+    const EMPTY: Self = Self { hash: 0, power: 1 };
+
+    fn leaf<T: Hash>(value: &T) -> Self {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+
+        Self {
+            hash: hasher.finish(),
+            power: BASE,
+        }
+    }
+
+    /// Summary of the run self followed by the run next.
+    fn then(self, next: Self) -> Self {
+        Self {
+            hash: self.hash.wrapping_mul(next.power).wrapping_add(next.hash),
+            power: self.power.wrapping_mul(next.power),
+        }
+    }
+}
+
+impl<T: Hash> Augment<T> for MerkleSummary {
+    fn compute(value: &T, left: Option<&Self>, right: Option<&Self>) -> Self {
+        let left = left.copied().unwrap_or(Self::EMPTY);
+        let right = right.copied().unwrap_or(Self::EMPTY);
+
+        left.then(Self::leaf(value)).then(right)
+    }
+}
+
+type MerkleTree<T> = AVLTree<T, MerkleSummary>;
+
+#[derive(Debug, Clone)]
+/// An ordered set whose contents are identified by a hash, for fast equality checks and diffs.
+///
+/// Hashes use the standard library's default hasher, so they can be compared
+/// between processes running the same build.
+pub struct MerkleSet<T: Ord + Hash> {
+    root: MerkleTree<T>,
+}
+
+impl<T: Ord + Hash> Default for MerkleSet<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Ord + Hash> PartialEq for MerkleSet<T> {
+    /// Compares the root hashes first, and the elements only when they match.
+    fn eq(&self, other: &Self) -> bool {
+        self.summary() == other.summary() && self.iter().eq(other.iter())
+    }
+}
+
+impl<T: Ord + Hash> MerkleSet<T> {
+    /// Creates a new, empty MerkleSet.
+    pub fn new() -> Self {
+        Self { root: None }
+    }
+
+    /// Returns the number of elements in the set.
+    pub fn len(&self) -> usize {
+        self.root.as_ref().map_or(0, |node| node.size)
+    }
+
+    /// Returns true if the set contains no elements.
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    /// Hash of the elements of the set. Equal sets have equal hashes, however they were built.
+    pub fn root_hash(&self) -> u64 {
+        self.summary().hash
+    }
+
+    fn summary(&self) -> MerkleSummary {
+        self.root.as_ref().map_or(MerkleSummary::EMPTY, |node| node.summary)
+    }
+
+    /// Adds a value to the set. Returns true if the set did not have an equal element present.
+    pub fn insert(&mut self, value: T) -> bool {
+        let (index, found) = position(&self.root, &value);
+        if found {
+            return false;
+        }
+
+        let (left, right) = split_at(self.root.take(), index);
+        self.root = Some(join(left, Box::new(AVLNode::new(value)), right));
+        true
+    }
+
+    /// Removes and returns the element equal to the value, if any.
+    pub fn take(&mut self, value: &T) -> Option<T> {
+        let (index, found) = position(&self.root, value);
+        if !found {
+            return None;
+        }
+
+        let (left, rest) = split_at(self.root.take(), index);
+        let (taken, right) = split_at(rest, 1);
+        self.root = join2(left, right);
+        taken.map(|node| node.value)
+    }
+
+    /// Returns true if the set contains an element equal to the value.
+    pub fn contains(&self, value: &T) -> bool {
+        position(&self.root, value).1
+    }
+
+    /// Gets an iterator that visits the elements in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = &'_ T> + '_ {
+        AVLTreeSetNodeIter {
+            prev_nodes: Vec::new(),
+            current_tree: &self.root,
+        }
+        .map(|node| &node.value)
+    }
+
+    /// Finds the elements only in self and those only in other, in ascending order.
+    ///
+    /// Subtrees of self whose elements hash the same as the matching range of other
+    /// are skipped, so similar sets are compared in far fewer than n steps.
+    pub fn diff<'a>(&'a self, other: &'a Self) -> MerkleDiff<'a, T> {
+        let mut diff = MerkleDiff {
+            only_in_self: Vec::new(),
+            only_in_other: Vec::new(),
+        };
+
+        if self.summary() != other.summary() {
+            diff_range(&self.root, &other.root, Bound::Unbounded, Bound::Unbounded, &mut diff);
+        }
+
+        diff
+    }
+}
+
+impl<T: Ord + Hash> FromIterator<T> for MerkleSet<T> {
+    /// Create a MerkleSet from an iterator, building the tree in O(n) after sorting.
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut values = iter.into_iter().collect::<Vec<_>>();
+        values.sort();
+        values.dedup();
+
+        Self {
+            root: build(values),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+/// The differences between two MerkleSets.
+pub struct MerkleDiff<'a, T> {
+    /// Elements in the first set but not in the second, ascending.
+    pub only_in_self: Vec<&'a T>,
+    /// Elements in the second set but not in the first, ascending.
+    pub only_in_other: Vec<&'a T>,
+}
+
+impl<T> MerkleDiff<'_, T> {
+    /// Returns true if the sets have the same elements.
+    pub fn is_empty(&self) -> bool {
+        self.only_in_self.is_empty() && self.only_in_other.is_empty()
+    }
+}
+
+/// Number of elements less than value, and whether value is in the tree.
+fn position<T: Ord + Hash>(tree: &MerkleTree<T>, value: &T) -> (usize, bool) {
+    let mut current_tree = tree;
+    let mut index = 0;
+
+    while let Some(current_node) = current_tree {
+        match current_node.value.cmp(value) {
+            Ordering::Greater => current_tree = &current_node.left,
+            Ordering::Equal => return (index + current_node.left_size(), true),
+            Ordering::Less => {
+                index += current_node.left_size() + 1;
+                current_tree = &current_node.right;
+            }
+        }
+    }
+
+    (index, false)
+}
+
+fn above<T: Ord>(value: &T, low: Bound<&T>) -> bool {
+    match low {
+        Bound::Excluded(low) => value > low,
+        _ => true,
+    }
+}
+
+fn below<T: Ord>(value: &T, high: Bound<&T>) -> bool {
+    match high {
+        Bound::Excluded(high) => value < high,
+        _ => true,
+    }
+}
+
+/// Summary of the elements of tree strictly between low and high, from O(log n) cached summaries.
+fn range_summary<T: Ord + Hash>(tree: &MerkleTree<T>, low: Bound<&T>, high: Bound<&T>) -> MerkleSummary {
+    let Some(node) = tree else {
+        return MerkleSummary::EMPTY;
+    };
+
+    if let (Bound::Unbounded, Bound::Unbounded) = (low, high) {
+        return node.summary;
+    }
+
+    if !above(&node.value, low) {
+        return range_summary(&node.right, low, high);
+    }
+    if !below(&node.value, high) {
+        return range_summary(&node.left, low, high);
+    }
+
+    // The node splits the range, so each side is bounded on one end only
+    let left = range_summary(&node.left, low, Bound::Unbounded);
+    let right = range_summary(&node.right, Bound::Unbounded, high);
+    left.then(MerkleSummary::leaf(&node.value)).then(right)
+}
+
+/// Elements of tree strictly between low and high, in ascending order.
+fn collect_range<'a, T: Ord>(tree: &'a MerkleTree<T>, low: Bound<&T>, high: Bound<&T>, out: &mut Vec<&'a T>) {
+    if let Some(node) = tree {
+        let is_above = above(&node.value, low);
+        let is_below = below(&node.value, high);
+
+        if is_above {
+            collect_range(&node.left, low, high, out);
+        }
+        if is_above && is_below {
+            out.push(&node.value);
+        }
+        if is_below {
+            collect_range(&node.right, low, high, out);
+        }
+    }
+}
+
+/// Diffs the subtree ours, holding every element of our set between low and high,
+/// against the elements of theirs in the same range.
+fn diff_range<'a, T: Ord + Hash>(
+    ours: &'a MerkleTree<T>,
+    theirs: &'a MerkleTree<T>,
+    low: Bound<&'a T>,
+    high: Bound<&'a T>,
+    diff: &mut MerkleDiff<'a, T>,
+) {
+    let Some(node) = ours else {
+        collect_range(theirs, low, high, &mut diff.only_in_other);
+        return;
+    };
+
+    if node.summary == range_summary(theirs, low, high) {
+        return;
+    }
+
+    diff_range(&node.left, theirs, low, Bound::Excluded(&node.value), diff);
+    if !position(theirs, &node.value).1 {
+        diff.only_in_self.push(&node.value);
+    }
+    diff_range(&node.right, theirs, Bound::Excluded(&node.value), high, diff);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    #[quickcheck]
+    fn hash_ignores_shape(xs: Vec<u16>, takes: Vec<u16>) -> bool {
+        let mut inserted = MerkleSet::new();
+        let mut reversed = MerkleSet::new();
+        for &x in &xs {
+            inserted.insert(x);
+        }
+        for &x in xs.iter().rev() {
+            reversed.insert(x);
+        }
+        for y in &takes {
+            inserted.take(y);
+            reversed.take(y);
+        }
+
+        let built = xs
+            .iter()
+            .filter(|x| !takes.contains(x))
+            .cloned()
+            .collect::<MerkleSet<_>>();
+
+        inserted.root_hash() == reversed.root_hash()
+            && inserted.root_hash() == built.root_hash()
+            && inserted == built
+            && inserted.iter().eq(built.iter())
+    }
+
+    #[quickcheck]
+    fn set_parity(xs: Vec<u16>, ys: Vec<u16>) -> bool {
+        let mut set = MerkleSet::new();
+        let mut model = BTreeSet::new();
+
+        xs.iter().all(|&x| set.insert(x) == model.insert(x))
+            && ys.iter().all(|y| set.contains(y) == model.contains(y))
+            && ys.iter().all(|y| set.take(y) == model.take(y))
+            && set.len() == model.len()
+            && set.iter().eq(model.iter())
+    }
+
+    #[quickcheck]
+    fn diff_parity(xs: Vec<u16>, only_ours: Vec<u16>, only_theirs: Vec<u16>) -> bool {
+        let ours = xs.iter().chain(&only_ours).cloned().collect::<MerkleSet<_>>();
+        let theirs = xs.iter().chain(&only_theirs).cloned().collect::<MerkleSet<_>>();
+        let ours_model = ours.iter().cloned().collect::<BTreeSet<_>>();
+        let theirs_model = theirs.iter().cloned().collect::<BTreeSet<_>>();
+
+        let diff = ours.diff(&theirs);
+
+        diff.only_in_self.into_iter().eq(ours_model.difference(&theirs_model))
+            && diff.only_in_other.into_iter().eq(theirs_model.difference(&ours_model))
+            && ours.diff(&theirs).is_empty() == (ours_model == theirs_model)
+            && (ours.root_hash() == theirs.root_hash()) == (ours_model == theirs_model)
+    }
+
+    #[test]
+    fn diff_skips_equal_subtrees() {
+        let ours = (0..10_000u32).collect::<MerkleSet<_>>();
+        let mut theirs = ours.clone();
+        theirs.take(&1234);
+        theirs.insert(20_000);
+
+        let diff = ours.diff(&theirs);
+        assert_eq!(diff.only_in_self, [&1234]);
+        assert_eq!(diff.only_in_other, [&20_000]);
+        assert!(ours.diff(&ours).is_empty());
+        assert_ne!(ours.root_hash(), theirs.root_hash());
+    }
+}
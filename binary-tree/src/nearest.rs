@@ -0,0 +1,238 @@
+/*
+    Nearest neighbour search.
+    The elements below the query and those at or above it are walked outward
+    from the query at the same time, always taking whichever is closer.
+*/
+
+use std::cmp::Ordering;
+use std::iter::{Peekable, Rev};
+
+use crate::{AVLTreeSet, AVLTreeSetRange, AvlPolicy, BalancePolicy};
+
+/// Distance between two values, consistent with their order.
+///
+/// For a <= b <= c, a.distance(b) and b.distance(c) must each be at most a.distance(c).
+pub trait Distance {
+    type Output: Ord;
+
+    fn distance(&self, other: &Self) -> Self::Output;
+}
+
+macro_rules! impl_distance_for_int {
+    ($($int:ty => $unsigned:ty),*) => {
+        $(
+            impl Distance for $int {
+                type Output = $unsigned;
+
+                fn distance(&self, other: &Self) -> Self::Output {
+                    self.abs_diff(*other)
+                }
+            }
+        )*
+    };
+}
+
+impl_distance_for_int!(
+    u8 => u8, u16 => u16, u32 => u32, u64 => u64, u128 => u128, usize => usize,
+    i8 => u8, i16 => u16, i32 => u32, i64 => u64, i128 => u128, isize => usize
+);
+
+macro_rules! total_float {
+    ($($name:ident($float:ty)),*) => {
+        $(
+            #[derive(Debug, Clone, Copy, Default)]
+            /// A float ordered by total_cmp, so it can be stored in a set.
+            pub struct $name(pub $float);
+
+            impl PartialEq for $name {
+                fn eq(&self, other: &Self) -> bool {
+                    self.cmp(other) == Ordering::Equal
+                }
+            }
+
+            impl Eq for $name {}
+
+            impl PartialOrd for $name {
+                fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+                    Some(self.cmp(other))
+                }
+            }
+
+            impl Ord for $name {
+                fn cmp(&self, other: &Self) -> Ordering {
+                    self.0.total_cmp(&other.0)
+                }
+            }
+
+            impl Distance for $name {
+                type Output = $name;
+
+                fn distance(&self, other: &Self) -> Self::Output {
+                    $name((self.0 - other.0).abs())
+                }
+            }
+        )*
+    };
+}
+
+total_float!(TotalF32(f32), TotalF64(f64));
+
+impl<T: Ord + Distance, P: BalancePolicy> AVLTreeSet<T, P> {
+    /// Gets an iterator over the k elements closest to query, in order of increasing distance.
+    ///
+    /// Of two elements at the same distance the smaller comes first.
+    /// Takes O(log n + k).
+    pub fn nearest<'a>(&'a self, query: &'a T, k: usize) -> Nearest<'a, T, P> {
+        Nearest {
+            query,
+            below: self.range(..query).rev().peekable(),
+            above: self.range(query..).peekable(),
+            remaining: k,
+        }
+    }
+}
+
+#[derive(Debug)]
+/// An iterator over the elements of an AVLTreeSet closest to a query.
+pub struct Nearest<'a, T, P: BalancePolicy = AvlPolicy> {
+    query: &'a T,
+    /// Elements below the query, closest first
+    below: Peekable<Rev<AVLTreeSetRange<'a, T, P>>>,
+    /// Elements at or above the query, closest first
+    above: Peekable<AVLTreeSetRange<'a, T, P>>,
+    remaining: usize,
+}
+
+impl<'a, T: Distance, P: BalancePolicy> Iterator for Nearest<'a, T, P> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        let next = match (self.below.peek(), self.above.peek()) {
+            (Some(below), Some(above)) => {
+                if below.distance(self.query) <= above.distance(self.query) {
+                    self.below.next()
+                } else {
+                    self.above.next()
+                }
+            }
+            (Some(_), None) => self.below.next(),
+            (None, _) => self.above.next(),
+        };
+
+        if next.is_some() {
+            self.remaining -= 1;
+        }
+        next
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let available = self.below.len() + self.above.len();
+        let len = available.min(self.remaining);
+        (len, Some(len))
+    }
+}
+
+impl<T: Distance, P: BalancePolicy> ExactSizeIterator for Nearest<'_, T, P> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{TreapPolicy, WeightBalancedPolicy};
+
+    /// Nearest by sorting every element on distance.
+    fn brute_force<T: Ord + Distance + Clone>(xs: &[T], query: &T, k: usize) -> Vec<T> {
+        let mut xs = xs.to_vec();
+        xs.sort();
+        xs.dedup();
+        xs.sort_by(|a, b| a.distance(query).cmp(&b.distance(query)).then(a.cmp(b)));
+        xs.truncate(k);
+        xs
+    }
+
+    fn integers<P: BalancePolicy + Default>(xs: Vec<i16>, query: i16, k: u8) -> bool {
+        let set = xs.iter().cloned().collect::<AVLTreeSet<_, P>>();
+        let nearest = set.nearest(&query, k as usize);
+
+        nearest.len() == brute_force(&xs, &query, k as usize).len()
+            && nearest.cloned().eq(brute_force(&xs, &query, k as usize))
+    }
+
+    #[quickcheck]
+    fn nearest_integers(xs: Vec<i16>, query: i16, k: u8) -> bool {
+        integers::<AvlPolicy>(xs, query, k)
+    }
+
+    #[quickcheck]
+    fn weight_balanced_nearest_integers(xs: Vec<i16>, query: i16, k: u8) -> bool {
+        integers::<WeightBalancedPolicy>(xs, query, k)
+    }
+
+    #[quickcheck]
+    fn treap_nearest_integers(xs: Vec<i16>, query: i16, k: u8) -> bool {
+        integers::<TreapPolicy>(xs, query, k)
+    }
+
+    fn unsigned_extremes<P: BalancePolicy + Default>(xs: Vec<u64>, k: u8) -> bool {
+        let set = xs.iter().cloned().collect::<AVLTreeSet<_, P>>();
+
+        [0, u64::MAX].iter().all(|query| {
+            set.nearest(query, k as usize)
+                .cloned()
+                .eq(brute_force(&xs, query, k as usize))
+        })
+    }
+
+    #[quickcheck]
+    fn nearest_unsigned_extremes(xs: Vec<u64>, k: u8) -> bool {
+        unsigned_extremes::<AvlPolicy>(xs, k)
+    }
+
+    #[quickcheck]
+    fn weight_balanced_nearest_unsigned_extremes(xs: Vec<u64>, k: u8) -> bool {
+        unsigned_extremes::<WeightBalancedPolicy>(xs, k)
+    }
+
+    #[quickcheck]
+    fn treap_nearest_unsigned_extremes(xs: Vec<u64>, k: u8) -> bool {
+        unsigned_extremes::<TreapPolicy>(xs, k)
+    }
+
+    fn floats<P: BalancePolicy + Default>(xs: Vec<i16>, query: i16, k: u8) -> bool {
+        // Halves keep the arithmetic exact, so distances compare the same both ways
+        let xs = xs.into_iter().map(|x| TotalF64(x as f64 / 2.0)).collect::<Vec<_>>();
+        let query = TotalF64(query as f64 / 2.0);
+        let set = xs.iter().cloned().collect::<AVLTreeSet<_, P>>();
+
+        set.nearest(&query, k as usize)
+            .cloned()
+            .eq(brute_force(&xs, &query, k as usize))
+    }
+
+    #[quickcheck]
+    fn nearest_floats(xs: Vec<i16>, query: i16, k: u8) -> bool {
+        floats::<AvlPolicy>(xs, query, k)
+    }
+
+    #[quickcheck]
+    fn weight_balanced_nearest_floats(xs: Vec<i16>, query: i16, k: u8) -> bool {
+        floats::<WeightBalancedPolicy>(xs, query, k)
+    }
+
+    #[quickcheck]
+    fn treap_nearest_floats(xs: Vec<i16>, query: i16, k: u8) -> bool {
+        floats::<TreapPolicy>(xs, query, k)
+    }
+
+    #[test]
+    fn ties_take_smaller_first() {
+        let set = [1, 3, 5, 7].into_iter().collect::<AVLTreeSet<i32>>();
+
+        assert_eq!(set.nearest(&4, 3).collect::<Vec<_>>(), [&3, &5, &1]);
+        assert_eq!(set.nearest(&5, 10).collect::<Vec<_>>(), [&5, &3, &7, &1]);
+        assert_eq!(set.nearest(&5, 0).count(), 0);
+    }
+}
@@ -0,0 +1,196 @@
+/*
+    Whole subtree operations shared by the trees in this crate.
+    Joining two trees around a middle node is the building block for
+    splitting, concatenating and bulk edits in O(log n).
+*/
+
+use std::cmp::Ordering;
+
+use crate::{AVLNode, AVLTree, BalancePolicy};
+
+/// Extra data cached in every node, computed from the node's value and its children.
+///
+/// Recomputed whenever the height of a node is, so it stays correct through rotations.
+/// Public only within the crate, as this module is private.
+pub trait Augment<T> {
+    fn compute(value: &T, left: Option<&Self>, right: Option<&Self>) -> Self;
+}
+
+impl<T> Augment<T> for () {
+    fn compute(_value: &T, _left: Option<&Self>, _right: Option<&Self>) -> Self {}
+}
+
+/// Joins `left`, `mid` and `right` into one balanced tree.
+///
+/// Every value in `left` must come before `mid` and every value in `right` after it.
+/// Runs in O(|height(left) - height(right)|).
+pub(crate) fn join<T, A: Augment<T>>(left: AVLTree<T, A>, mut mid: Box<AVLNode<T, A>>, right: AVLTree<T, A>) -> Box<AVLNode<T, A>> {
+    let left_height = left.as_ref().map_or(0, |node| node.height);
+    let right_height = right.as_ref().map_or(0, |node| node.height);
+
+    if left_height > right_height + 1 {
+        // Walk down the right spine of the taller left tree
+        let mut node = left.unwrap();
+        node.right = Some(join(node.right.take(), mid, right));
+        node.update_height();
+        node.rebalance();
+        node
+    } else if right_height > left_height + 1 {
+        // Walk down the left spine of the taller right tree
+        let mut node = right.unwrap();
+        node.left = Some(join(left, mid, node.left.take()));
+        node.update_height();
+        node.rebalance();
+        node
+    } else {
+        mid.left = left;
+        mid.right = right;
+        mid.update_height();
+        mid
+    }
+}
+
+/// Joins two trees where every value in `left` comes before every value in `right`.
+pub(crate) fn join2<T, A: Augment<T>>(left: AVLTree<T, A>, right: AVLTree<T, A>) -> AVLTree<T, A> {
+    match left {
+        None => right,
+        Some(left) => {
+            let (rest, last) = split_last(left);
+            Some(join(rest, last, right))
+        }
+    }
+}
+
+/// Detaches the first node of a tree. Returns the rest of the tree and the detached node.
+pub(crate) fn split_first<T, A: Augment<T>>(mut node: Box<AVLNode<T, A>>) -> (AVLTree<T, A>, Box<AVLNode<T, A>>) {
+    match node.left.take() {
+        None => {
+            let rest = node.right.take();
+            node.update_height();
+            (rest, node)
+        }
+        Some(left) => {
+            let (rest, first) = split_first(left);
+            node.left = rest;
+            node.update_height();
+            node.rebalance();
+            (Some(node), first)
+        }
+    }
+}
+
+/// Detaches the last node of a tree. Returns the rest of the tree and the detached node.
+pub(crate) fn split_last<T, A: Augment<T>>(mut node: Box<AVLNode<T, A>>) -> (AVLTree<T, A>, Box<AVLNode<T, A>>) {
+    match node.right.take() {
+        None => {
+            let rest = node.left.take();
+            node.update_height();
+            (rest, node)
+        }
+        Some(right) => {
+            let (rest, last) = split_last(right);
+            node.right = rest;
+            node.update_height();
+            node.rebalance();
+            (Some(node), last)
+        }
+    }
+}
+
+/// Splits a tree into the first `index` nodes and the rest.
+pub(crate) fn split_at<T, A: Augment<T>>(tree: AVLTree<T, A>, index: usize) -> (AVLTree<T, A>, AVLTree<T, A>) {
+    let mut node = match tree {
+        Some(node) => node,
+        None => return (None, None),
+    };
+
+    let left = node.left.take();
+    let right = node.right.take();
+    let left_size = left.as_ref().map_or(0, |node| node.size);
+
+    match index.cmp(&left_size) {
+        Ordering::Less => {
+            let (left_left, left_right) = split_at(left, index);
+            (left_left, Some(join(left_right, node, right)))
+        }
+        Ordering::Equal => (left, Some(join(None, node, right))),
+        Ordering::Greater => {
+            let (right_left, right_right) = split_at(right, index - left_size - 1);
+            (Some(join(left, node, right_left)), right_right)
+        }
+    }
+}
+
+/// Returns the node at position index, if any.
+pub(crate) fn get_at<T, A, P: BalancePolicy>(tree: &AVLTree<T, A, P>, mut index: usize) -> Option<&AVLNode<T, A, P>> {
+    let mut current_tree = tree;
+
+    while let Some(current_node) = current_tree {
+        let left_size = current_node.left.as_ref().map_or(0, |node| node.size);
+        match index.cmp(&left_size) {
+            Ordering::Less => current_tree = &current_node.left,
+            Ordering::Equal => return Some(current_node),
+            Ordering::Greater => {
+                index -= left_size + 1;
+                current_tree = &current_node.right;
+            }
+        }
+    }
+    None
+}
+
+/// Returns the node at position index mutably, if any.
+///
+/// Changing the value must not change its summary or its place in the order.
+pub(crate) fn get_at_mut<T, A, P: BalancePolicy>(
+    tree: &mut AVLTree<T, A, P>,
+    mut index: usize,
+) -> Option<&mut AVLNode<T, A, P>> {
+    let mut current_tree = tree;
+
+    while let Some(current_node) = current_tree {
+        let left_size = current_node.left.as_ref().map_or(0, |node| node.size);
+        match index.cmp(&left_size) {
+            Ordering::Less => current_tree = &mut current_node.left,
+            Ordering::Equal => return Some(current_node),
+            Ordering::Greater => {
+                index -= left_size + 1;
+                current_tree = &mut current_node.right;
+            }
+        }
+    }
+    None
+}
+
+/// Builds a balanced tree from values already in tree order in O(n).
+pub(crate) fn build<T, A: Augment<T>>(values: Vec<T>) -> AVLTree<T, A> {
+    let len = values.len();
+    let mut values = values.into_iter();
+    build_from(&mut values, len)
+}
+
+/// Builds a balanced tree from the next `len` values of the iterator.
+pub(crate) fn build_from<T, A: Augment<T>>(values: &mut impl Iterator<Item = T>, len: usize) -> AVLTree<T, A> {
+    if len == 0 {
+        return None;
+    }
+
+    let left_len = len / 2;
+    let left = build_from(values, left_len);
+    let mut node = Box::new(AVLNode::new(values.next().unwrap()));
+    node.left = left;
+    node.right = build_from(values, len - left_len - 1);
+    node.update_height();
+
+    Some(node)
+}
+
+/// Moves the values of a tree into out in tree order.
+pub(crate) fn into_sorted<T, A, P: BalancePolicy>(tree: AVLTree<T, A, P>, out: &mut Vec<T>) {
+    if let Some(node) = tree {
+        let node = *node;
+        into_sorted(node.left, out);
+        out.push(node.value);
+        into_sorted(node.right, out);
+    }
+}
@@ -0,0 +1,420 @@
+/*
+    Common interface for the ordered collections in this crate and the standard library.
+    Lets code, tests and benchmarks be written once and run against every backend.
+*/
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::RangeBounds;
+
+use crate::{AVLTreeMap, AVLTreeSet, BalancePolicy, RedBlackTree};
+
+/// An ordered set of unique elements.
+pub trait OrderedSet<T: Ord>: Default {
+    /// Adds a value to the set. Returns true if the set did not have an equal element present.
+    fn insert(&mut self, value: T) -> bool;
+
+    /// Removes and returns the element in the set, if any, that is equal to the value.
+    fn take(&mut self, value: &T) -> Option<T>;
+
+    /// Removes the element equal to the value. Returns true if there was one.
+    fn remove(&mut self, value: &T) -> bool {
+        self.take(value).is_some()
+    }
+
+    /// Returns true if the set contains an element equal to the value.
+    fn contains(&self, value: &T) -> bool;
+
+    /// Returns the number of elements in the set.
+    fn len(&self) -> usize;
+
+    /// Returns true if the set contains no elements.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Gets an iterator that visits the elements in ascending order.
+    fn iter<'a>(&'a self) -> impl Iterator<Item = &'a T>
+    where
+        T: 'a;
+
+    /// Gets an iterator that visits the elements in range in ascending order.
+    fn range<'a, R: RangeBounds<T>>(&'a self, range: R) -> impl Iterator<Item = &'a T>
+    where
+        T: 'a;
+
+    /// Returns the smallest element, if any.
+    fn first(&self) -> Option<&T>;
+
+    /// Returns the largest element, if any.
+    fn last(&self) -> Option<&T>;
+}
+
+/// An ordered map from unique keys to values.
+pub trait OrderedMap<K: Ord, V>: Default {
+    /// Inserts a value for key. Returns the old value if the key was already present.
+    fn insert(&mut self, key: K, value: V) -> Option<V>;
+
+    /// Removes the entry for key and returns its value, if any.
+    fn remove(&mut self, key: &K) -> Option<V>;
+
+    /// Returns a reference to the value for key, if any.
+    fn get(&self, key: &K) -> Option<&V>;
+
+    /// Returns true if the map contains an entry for key.
+    fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Returns the number of entries in the map.
+    fn len(&self) -> usize;
+
+    /// Returns true if the map contains no entries.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Gets an iterator that visits the entries in ascending key order.
+    fn iter<'a>(&'a self) -> impl Iterator<Item = (&'a K, &'a V)>
+    where
+        K: 'a,
+        V: 'a;
+
+    /// Gets an iterator that visits the entries with keys in range in ascending key order.
+    fn range<'a, R: RangeBounds<K>>(&'a self, range: R) -> impl Iterator<Item = (&'a K, &'a V)>
+    where
+        K: 'a,
+        V: 'a;
+
+    /// Returns the entry with the smallest key, if any.
+    fn first(&self) -> Option<(&K, &V)>;
+
+    /// Returns the entry with the largest key, if any.
+    fn last(&self) -> Option<(&K, &V)>;
+}
+
+impl<T: Ord, P: BalancePolicy + Default> OrderedSet<T> for AVLTreeSet<T, P> {
+    fn insert(&mut self, value: T) -> bool {
+        AVLTreeSet::insert(self, value)
+    }
+
+    fn take(&mut self, value: &T) -> Option<T> {
+        AVLTreeSet::take(self, value)
+    }
+
+    fn contains(&self, value: &T) -> bool {
+        AVLTreeSet::contains(self, value)
+    }
+
+    fn len(&self) -> usize {
+        AVLTreeSet::len(self)
+    }
+
+    fn iter<'a>(&'a self) -> impl Iterator<Item = &'a T>
+    where
+        T: 'a,
+    {
+        AVLTreeSet::iter(self)
+    }
+
+    fn range<'a, R: RangeBounds<T>>(&'a self, range: R) -> impl Iterator<Item = &'a T>
+    where
+        T: 'a,
+    {
+        AVLTreeSet::range(self, range)
+    }
+
+    fn first(&self) -> Option<&T> {
+        AVLTreeSet::first(self)
+    }
+
+    fn last(&self) -> Option<&T> {
+        AVLTreeSet::last(self)
+    }
+}
+
+impl<T: Ord> OrderedSet<T> for RedBlackTree<T, ()> {
+    fn insert(&mut self, value: T) -> bool {
+        RedBlackTree::insert(self, value, ()).is_none()
+    }
+
+    fn take(&mut self, value: &T) -> Option<T> {
+        RedBlackTree::remove_entry(self, value).map(|(key, _)| key)
+    }
+
+    fn contains(&self, value: &T) -> bool {
+        RedBlackTree::contains_key(self, value)
+    }
+
+    fn len(&self) -> usize {
+        RedBlackTree::len(self)
+    }
+
+    fn iter<'a>(&'a self) -> impl Iterator<Item = &'a T>
+    where
+        T: 'a,
+    {
+        RedBlackTree::keys(self)
+    }
+
+    fn range<'a, R: RangeBounds<T>>(&'a self, range: R) -> impl Iterator<Item = &'a T>
+    where
+        T: 'a,
+    {
+        RedBlackTree::range(self, range).map(|(key, _)| key)
+    }
+
+    fn first(&self) -> Option<&T> {
+        RedBlackTree::first_key_value(self).map(|(key, _)| key)
+    }
+
+    fn last(&self) -> Option<&T> {
+        RedBlackTree::last_key_value(self).map(|(key, _)| key)
+    }
+}
+
+impl<T: Ord> OrderedSet<T> for BTreeSet<T> {
+    fn insert(&mut self, value: T) -> bool {
+        BTreeSet::insert(self, value)
+    }
+
+    fn take(&mut self, value: &T) -> Option<T> {
+        BTreeSet::take(self, value)
+    }
+
+    fn contains(&self, value: &T) -> bool {
+        BTreeSet::contains(self, value)
+    }
+
+    fn len(&self) -> usize {
+        BTreeSet::len(self)
+    }
+
+    fn iter<'a>(&'a self) -> impl Iterator<Item = &'a T>
+    where
+        T: 'a,
+    {
+        BTreeSet::iter(self)
+    }
+
+    fn range<'a, R: RangeBounds<T>>(&'a self, range: R) -> impl Iterator<Item = &'a T>
+    where
+        T: 'a,
+    {
+        BTreeSet::range(self, range)
+    }
+
+    fn first(&self) -> Option<&T> {
+        BTreeSet::first(self)
+    }
+
+    fn last(&self) -> Option<&T> {
+        BTreeSet::last(self)
+    }
+}
+
+impl<K: Ord, V> OrderedMap<K, V> for AVLTreeMap<K, V> {
+    fn insert(&mut self, key: K, value: V) -> Option<V> {
+        AVLTreeMap::insert(self, key, value)
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        AVLTreeMap::remove(self, key)
+    }
+
+    fn get(&self, key: &K) -> Option<&V> {
+        AVLTreeMap::get(self, key)
+    }
+
+    fn len(&self) -> usize {
+        AVLTreeMap::len(self)
+    }
+
+    fn iter<'a>(&'a self) -> impl Iterator<Item = (&'a K, &'a V)>
+    where
+        K: 'a,
+        V: 'a,
+    {
+        AVLTreeMap::iter(self)
+    }
+
+    fn range<'a, R: RangeBounds<K>>(&'a self, range: R) -> impl Iterator<Item = (&'a K, &'a V)>
+    where
+        K: 'a,
+        V: 'a,
+    {
+        AVLTreeMap::range(self, range)
+    }
+
+    fn first(&self) -> Option<(&K, &V)> {
+        AVLTreeMap::first_key_value(self)
+    }
+
+    fn last(&self) -> Option<(&K, &V)> {
+        AVLTreeMap::last_key_value(self)
+    }
+}
+
+impl<K: Ord, V> OrderedMap<K, V> for RedBlackTree<K, V> {
+    fn insert(&mut self, key: K, value: V) -> Option<V> {
+        RedBlackTree::insert(self, key, value)
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        RedBlackTree::remove(self, key)
+    }
+
+    fn get(&self, key: &K) -> Option<&V> {
+        RedBlackTree::get(self, key)
+    }
+
+    fn len(&self) -> usize {
+        RedBlackTree::len(self)
+    }
+
+    fn iter<'a>(&'a self) -> impl Iterator<Item = (&'a K, &'a V)>
+    where
+        K: 'a,
+        V: 'a,
+    {
+        RedBlackTree::iter(self)
+    }
+
+    fn range<'a, R: RangeBounds<K>>(&'a self, range: R) -> impl Iterator<Item = (&'a K, &'a V)>
+    where
+        K: 'a,
+        V: 'a,
+    {
+        RedBlackTree::range(self, range)
+    }
+
+    fn first(&self) -> Option<(&K, &V)> {
+        RedBlackTree::first_key_value(self)
+    }
+
+    fn last(&self) -> Option<(&K, &V)> {
+        RedBlackTree::last_key_value(self)
+    }
+}
+
+impl<K: Ord, V> OrderedMap<K, V> for BTreeMap<K, V> {
+    fn insert(&mut self, key: K, value: V) -> Option<V> {
+        BTreeMap::insert(self, key, value)
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        BTreeMap::remove(self, key)
+    }
+
+    fn get(&self, key: &K) -> Option<&V> {
+        BTreeMap::get(self, key)
+    }
+
+    fn len(&self) -> usize {
+        BTreeMap::len(self)
+    }
+
+    fn iter<'a>(&'a self) -> impl Iterator<Item = (&'a K, &'a V)>
+    where
+        K: 'a,
+        V: 'a,
+    {
+        BTreeMap::iter(self)
+    }
+
+    fn range<'a, R: RangeBounds<K>>(&'a self, range: R) -> impl Iterator<Item = (&'a K, &'a V)>
+    where
+        K: 'a,
+        V: 'a,
+    {
+        BTreeMap::range(self, range)
+    }
+
+    fn first(&self) -> Option<(&K, &V)> {
+        BTreeMap::first_key_value(self)
+    }
+
+    fn last(&self) -> Option<(&K, &V)> {
+        BTreeMap::last_key_value(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{TreapPolicy, WeightBalancedPolicy};
+
+    /// Runs the same operations on a set and on a BTreeSet model and compares every result.
+    fn set_conforms<S: OrderedSet<i16>>(xs: Vec<i16>, ys: Vec<i16>, a: i16, b: i16) -> bool {
+        let mut set = S::default();
+        let mut model = BTreeSet::new();
+        let (low, high) = (a.min(b), a.max(b));
+
+        let inserts = xs.iter().all(|&x| set.insert(x) == model.insert(x));
+        let queries = ys.iter().all(|y| set.contains(y) == model.contains(y))
+            && set.len() == model.len()
+            && set.first() == model.first()
+            && set.last() == model.last()
+            && set.iter().eq(model.iter())
+            && set.range(low..high).eq(model.range(low..high))
+            && set.range(low..=high).eq(model.range(low..=high))
+            && set.range(..).eq(model.range(..));
+        let takes = ys.iter().all(|y| set.take(y) == model.take(y));
+
+        inserts && queries && takes && set.iter().eq(model.iter())
+    }
+
+    /// Runs the same operations on a map and on a BTreeMap model and compares every result.
+    fn map_conforms<M: OrderedMap<i16, u8>>(xs: Vec<(i16, u8)>, ys: Vec<i16>, a: i16, b: i16) -> bool {
+        let mut map = M::default();
+        let mut model = BTreeMap::new();
+        let (low, high) = (a.min(b), a.max(b));
+
+        let inserts = xs.iter().all(|&(k, v)| map.insert(k, v) == model.insert(k, v));
+        let queries = ys.iter().all(|y| map.get(y) == model.get(y) && map.contains_key(y) == model.contains_key(y))
+            && map.len() == model.len()
+            && map.first() == model.first_key_value()
+            && map.last() == model.last_key_value()
+            && map.iter().eq(model.iter())
+            && map.range(low..high).eq(model.range(low..high))
+            && map.range(low..=high).eq(model.range(low..=high));
+        let removes = ys.iter().all(|y| map.remove(y) == model.remove(y));
+
+        inserts && queries && removes && map.iter().eq(model.iter())
+    }
+
+    #[quickcheck]
+    fn avl_tree_set_conforms(xs: Vec<i16>, ys: Vec<i16>, a: i16, b: i16) -> bool {
+        set_conforms::<AVLTreeSet<_>>(xs, ys, a, b)
+    }
+
+    #[quickcheck]
+    fn red_black_tree_set_conforms(xs: Vec<i16>, ys: Vec<i16>, a: i16, b: i16) -> bool {
+        set_conforms::<RedBlackTree<_, ()>>(xs, ys, a, b)
+    }
+
+    #[quickcheck]
+    fn policy_tree_sets_conform(xs: Vec<i16>, ys: Vec<i16>, a: i16, b: i16) -> bool {
+        set_conforms::<AVLTreeSet<_, WeightBalancedPolicy>>(xs.clone(), ys.clone(), a, b)
+            && set_conforms::<AVLTreeSet<_, TreapPolicy>>(xs, ys, a, b)
+    }
+
+    #[quickcheck]
+    fn btree_set_conforms(xs: Vec<i16>, ys: Vec<i16>, a: i16, b: i16) -> bool {
+        set_conforms::<BTreeSet<_>>(xs, ys, a, b)
+    }
+
+    #[quickcheck]
+    fn avl_tree_map_conforms(xs: Vec<(i16, u8)>, ys: Vec<i16>, a: i16, b: i16) -> bool {
+        map_conforms::<AVLTreeMap<_, _>>(xs, ys, a, b)
+    }
+
+    #[quickcheck]
+    fn red_black_tree_map_conforms(xs: Vec<(i16, u8)>, ys: Vec<i16>, a: i16, b: i16) -> bool {
+        map_conforms::<RedBlackTree<_, _>>(xs, ys, a, b)
+    }
+
+    #[quickcheck]
+    fn btree_map_conforms(xs: Vec<(i16, u8)>, ys: Vec<i16>, a: i16, b: i16) -> bool {
+        map_conforms::<BTreeMap<_, _>>(xs, ys, a, b)
+    }
+}
@@ -0,0 +1,377 @@
+/*
+    Parallel iteration and bulk construction with rayon.
+    Only compiled with the `rayon` feature.
+
+    Every node knows the size of its subtree, so the iterator splits by
+    position and each half finds its ends in O(log n).
+*/
+
+use std::cmp::Ordering;
+
+use rayon::iter::plumbing::{bridge, Consumer, Producer, ProducerCallback, UnindexedConsumer};
+use rayon::prelude::*;
+
+use crate::ops::into_sorted;
+use crate::{AVLNode, AVLTree, AVLTreeSet, AVLTreeSetRange, AvlPolicy, BalancePolicy};
+
+impl<T: Ord + Sync, P: BalancePolicy + Sync> AVLTreeSet<T, P> {
+    /// Gets a parallel iterator that visits the elements in ascending order.
+    pub fn par_iter(&self) -> AVLTreeSetParIter<'_, T, P> {
+        AVLTreeSetParIter { set: self }
+    }
+}
+
+impl<'a, T: Ord + Sync, P: BalancePolicy + Sync> IntoParallelIterator for &'a AVLTreeSet<T, P> {
+    type Item = &'a T;
+    type Iter = AVLTreeSetParIter<'a, T, P>;
+
+    fn into_par_iter(self) -> Self::Iter {
+        self.par_iter()
+    }
+}
+
+#[derive(Debug)]
+/// A parallel iterator over the elements of an AVLTreeSet.
+pub struct AVLTreeSetParIter<'a, T: Ord, P: BalancePolicy = AvlPolicy> {
+    set: &'a AVLTreeSet<T, P>,
+}
+
+impl<'a, T: Ord + Sync, P: BalancePolicy + Sync> ParallelIterator for AVLTreeSetParIter<'a, T, P> {
+    type Item = &'a T;
+
+    fn drive_unindexed<C: UnindexedConsumer<Self::Item>>(self, consumer: C) -> C::Result {
+        bridge(self, consumer)
+    }
+
+    fn opt_len(&self) -> Option<usize> {
+        Some(self.set.len())
+    }
+}
+
+impl<T: Ord + Sync, P: BalancePolicy + Sync> IndexedParallelIterator for AVLTreeSetParIter<'_, T, P> {
+    fn len(&self) -> usize {
+        self.set.len()
+    }
+
+    fn drive<C: Consumer<Self::Item>>(self, consumer: C) -> C::Result {
+        bridge(self, consumer)
+    }
+
+    fn with_producer<CB: ProducerCallback<Self::Item>>(self, callback: CB) -> CB::Output {
+        callback.callback(PositionProducer {
+            root: &self.set.root,
+            start: 0,
+            end: self.set.len(),
+        })
+    }
+}
+
+/// The elements at positions start..end of a tree.
+struct PositionProducer<'a, T, P: BalancePolicy> {
+    root: &'a AVLTree<T, (), P>,
+    start: usize,
+    end: usize,
+}
+
+impl<'a, T: Sync, P: BalancePolicy + Sync> Producer for PositionProducer<'a, T, P> {
+    type Item = &'a T;
+    type IntoIter = AVLTreeSetRange<'a, T, P>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        if self.start == self.end {
+            return AVLTreeSetRange {
+                front: Vec::new(),
+                back: Vec::new(),
+                remaining: 0,
+            };
+        }
+
+        AVLTreeSetRange {
+            front: path_to(self.root, self.start, true),
+            back: path_to(self.root, self.end - 1, false),
+            remaining: self.end - self.start,
+        }
+    }
+
+    fn split_at(self, index: usize) -> (Self, Self) {
+        let middle = self.start + index;
+
+        (
+            Self {
+                end: middle,
+                ..self
+            },
+            Self {
+                start: middle,
+                ..self
+            },
+        )
+    }
+}
+
+/// Stack of nodes an AVLTreeSetRange keeps to continue from the element at position.
+///
+/// Going forward the nodes to the right of the path are pending, going backward those to the left.
+fn path_to<T, P: BalancePolicy>(root: &AVLTree<T, (), P>, mut position: usize, forward: bool) -> Vec<&AVLNode<T, (), P>> {
+    let mut path = Vec::new();
+    let mut current_tree = root;
+
+    while let Some(current_node) = current_tree {
+        let left_size = current_node.left_size();
+        match position.cmp(&left_size) {
+            Ordering::Less => {
+                if forward {
+                    path.push(&**current_node);
+                }
+                current_tree = &current_node.left;
+            }
+            Ordering::Equal => {
+                path.push(&**current_node);
+                break;
+            }
+            Ordering::Greater => {
+                if !forward {
+                    path.push(&**current_node);
+                }
+                position -= left_size + 1;
+                current_tree = &current_node.right;
+            }
+        }
+    }
+
+    path
+}
+
+impl<T: Ord + Send, P: BalancePolicy> ParallelExtend<T> for AVLTreeSet<T, P> {
+    /// Adds the values in parallel, keeping existing elements over equal new ones.
+    ///
+    /// Sorts the new values in parallel and rebuilds the tree in O(n).
+    /// If a comparison panics, the set is left as it was.
+    fn par_extend<I: IntoParallelIterator<Item = T>>(&mut self, par_iter: I) {
+        let added = sorted_unique(par_iter);
+        if added.is_empty() {
+            return;
+        }
+
+        // Plan the merge on the borrowed tree, so a panicking comparison leaves the set as it was
+        let mut steps = Vec::with_capacity(self.len() + added.len());
+        {
+            let mut existing = self.iter().peekable();
+            let mut new = added.iter().peekable();
+            while let (Some(old), Some(value)) = (existing.peek(), new.peek()) {
+                let step = old.cmp(value);
+                if step != Ordering::Greater {
+                    existing.next();
+                }
+                if step != Ordering::Less {
+                    new.next();
+                }
+                steps.push(step);
+            }
+        }
+
+        let mut existing = Vec::with_capacity(self.len());
+        into_sorted(self.root.take(), &mut existing);
+        self.len = 0;
+
+        // New values equal to an element are dropped once the set is whole again
+        let mut rejected = Vec::new();
+        let mut merged = Vec::with_capacity(existing.len() + added.len());
+        let mut existing = existing.into_iter();
+        let mut added = added.into_iter();
+        for step in steps {
+            match step {
+                Ordering::Less => merged.push(existing.next().unwrap()),
+                Ordering::Equal => {
+                    rejected.push(added.next().unwrap());
+                    merged.push(existing.next().unwrap());
+                }
+                Ordering::Greater => merged.push(added.next().unwrap()),
+            }
+        }
+        merged.extend(existing);
+        merged.extend(added);
+
+        self.append_sorted(merged);
+
+        #[cfg(feature = "debug-invariants")]
+        self.assert_invariants();
+    }
+}
+
+impl<T: Ord + Send, P: BalancePolicy + Default> FromParallelIterator<T> for AVLTreeSet<T, P> {
+    /// Collects the values in parallel. Of equal values the first is kept.
+    fn from_par_iter<I: IntoParallelIterator<Item = T>>(par_iter: I) -> Self {
+        let set = Self::from_sorted(sorted_unique(par_iter));
+
+        #[cfg(feature = "debug-invariants")]
+        set.assert_invariants();
+
+        set
+    }
+}
+
+/// Collects the values, sorted in parallel, keeping the first of equal values.
+fn sorted_unique<T: Ord + Send>(par_iter: impl IntoParallelIterator<Item = T>) -> Vec<T> {
+    let mut values = par_iter.into_par_iter().collect::<Vec<_>>();
+    values.par_sort();
+    values.dedup();
+    values
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{TreapPolicy, WeightBalancedPolicy};
+    use crate::tests::{dropped_once, values, Bomb, DROPS, FUSE};
+    use std::collections::BTreeSet;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    fn par_iter_order<P: BalancePolicy + Default + Sync>(xs: Vec<u16>) -> bool {
+        let set = xs.into_iter().collect::<AVLTreeSet<_, P>>();
+        let parallel = set.par_iter().collect::<Vec<_>>();
+
+        parallel.into_iter().eq(set.iter())
+    }
+
+    #[quickcheck]
+    fn par_iter_matches_iter(xs: Vec<u16>) -> bool {
+        par_iter_order::<AvlPolicy>(xs)
+    }
+
+    #[quickcheck]
+    fn weight_balanced_par_iter_matches_iter(xs: Vec<u16>) -> bool {
+        par_iter_order::<WeightBalancedPolicy>(xs)
+    }
+
+    #[quickcheck]
+    fn treap_par_iter_matches_iter(xs: Vec<u16>) -> bool {
+        par_iter_order::<TreapPolicy>(xs)
+    }
+
+    fn par_iter_pieces<P: BalancePolicy + Default + Sync>(xs: Vec<u16>, max_len: u8) -> bool {
+        let set = xs.into_iter().collect::<AVLTreeSet<_, P>>();
+        let expected = set.iter().collect::<Vec<_>>();
+
+        // Force small pieces, and check positions through enumerate
+        set.par_iter()
+            .with_max_len(max_len as usize % 4 + 1)
+            .enumerate()
+            .all(|(index, value)| expected[index] == value)
+            && set.par_iter().rev().collect::<Vec<_>>().into_iter().eq(expected.into_iter().rev())
+    }
+
+    #[quickcheck]
+    fn par_iter_splits(xs: Vec<u16>, max_len: u8) -> bool {
+        par_iter_pieces::<AvlPolicy>(xs, max_len)
+    }
+
+    #[quickcheck]
+    fn weight_balanced_par_iter_splits(xs: Vec<u16>, max_len: u8) -> bool {
+        par_iter_pieces::<WeightBalancedPolicy>(xs, max_len)
+    }
+
+    #[quickcheck]
+    fn treap_par_iter_splits(xs: Vec<u16>, max_len: u8) -> bool {
+        par_iter_pieces::<TreapPolicy>(xs, max_len)
+    }
+
+    fn from_par_iter<P: BalancePolicy + Default>(xs: Vec<u16>) -> bool {
+        let set = xs.par_iter().cloned().collect::<AVLTreeSet<_, P>>();
+        let expected = xs.into_iter().collect::<AVLTreeSet<_>>();
+
+        set.validate().is_ok() && set.iter().eq(expected.iter())
+    }
+
+    #[quickcheck]
+    fn from_par_iter_matches_collect(xs: Vec<u16>) -> bool {
+        from_par_iter::<AvlPolicy>(xs)
+    }
+
+    #[quickcheck]
+    fn weight_balanced_from_par_iter_matches_collect(xs: Vec<u16>) -> bool {
+        from_par_iter::<WeightBalancedPolicy>(xs)
+    }
+
+    #[quickcheck]
+    fn treap_from_par_iter_matches_collect(xs: Vec<u16>) -> bool {
+        from_par_iter::<TreapPolicy>(xs)
+    }
+
+    fn par_extend<P: BalancePolicy + Default + Clone>(xs: Vec<u16>, ys: Vec<u16>) -> bool {
+        let mut set = xs.iter().cloned().collect::<AVLTreeSet<_, P>>();
+        let mut expected = set.clone();
+        set.par_extend(ys.par_iter().cloned());
+        for y in ys {
+            expected.insert(y);
+        }
+
+        set.validate().is_ok() && set.iter().eq(expected.iter())
+    }
+
+    #[quickcheck]
+    fn par_extend_matches_insert(xs: Vec<u16>, ys: Vec<u16>) -> bool {
+        par_extend::<AvlPolicy>(xs, ys)
+    }
+
+    #[quickcheck]
+    fn weight_balanced_par_extend_matches_insert(xs: Vec<u16>, ys: Vec<u16>) -> bool {
+        par_extend::<WeightBalancedPolicy>(xs, ys)
+    }
+
+    #[quickcheck]
+    fn treap_par_extend_matches_insert(xs: Vec<u16>, ys: Vec<u16>) -> bool {
+        par_extend::<TreapPolicy>(xs, ys)
+    }
+
+    fn par_extend_atomic<P: BalancePolicy + Default>(xs: Vec<u8>, ys: Vec<u8>, fuse: u8) -> bool {
+        DROPS.take();
+        let mut ids = 0;
+        let mut set = AVLTreeSet::<_, P>::default();
+        for x in xs {
+            set.insert(Bomb::new(x % 64, &mut ids));
+        }
+
+        let before = values(&set).into_iter().collect::<BTreeSet<_>>();
+        let mut after = before.clone();
+        after.extend(ys.iter().map(|y| y % 64));
+        let added = ys.into_iter().map(|y| Bomb::new(y % 64, &mut ids)).collect::<Vec<_>>();
+
+        FUSE.set(Some(fuse));
+        let result = catch_unwind(AssertUnwindSafe(|| set.par_extend(added)));
+        FUSE.set(None);
+
+        // Either every value went in or the set was left alone
+        let contents = values(&set).into_iter().collect::<BTreeSet<_>>();
+        let consistent = (contents == after || (result.is_err() && contents == before))
+            && set.len() == contents.len()
+            && set.validate().is_ok();
+
+        drop(set);
+        consistent && dropped_once(ids)
+    }
+
+    #[quickcheck]
+    fn panicking_ord_keeps_par_extend_atomic(xs: Vec<u8>, ys: Vec<u8>, fuse: u8) -> bool {
+        par_extend_atomic::<AvlPolicy>(xs, ys, fuse)
+    }
+
+    #[quickcheck]
+    fn weight_balanced_panicking_ord_keeps_par_extend_atomic(xs: Vec<u8>, ys: Vec<u8>, fuse: u8) -> bool {
+        par_extend_atomic::<WeightBalancedPolicy>(xs, ys, fuse)
+    }
+
+    #[quickcheck]
+    fn treap_panicking_ord_keeps_par_extend_atomic(xs: Vec<u8>, ys: Vec<u8>, fuse: u8) -> bool {
+        par_extend_atomic::<TreapPolicy>(xs, ys, fuse)
+    }
+
+    #[test]
+    fn large_set() {
+        let set = (0..100_000u32).into_par_iter().rev().collect::<AVLTreeSet<_>>();
+
+        assert_eq!(set.len(), 100_000);
+        assert_eq!(set.par_iter().map(|&x| x as u64).sum::<u64>(), 4_999_950_000);
+        assert!(set.par_iter().cloned().eq((0..100_000u32).into_par_iter()));
+    }
+}
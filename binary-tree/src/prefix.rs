@@ -0,0 +1,141 @@
+/*
+    Prefix search for string and byte keys.
+    Keys sharing a prefix form one contiguous range in byte order:
+    from the prefix itself up to the last key starting with it.
+*/
+
+use crate::{AVLTreeSet, AVLTreeSetRange, BalancePolicy};
+
+impl<P: BalancePolicy> AVLTreeSet<String, P> {
+    /// Gets an iterator over the keys starting with prefix, in ascending order.
+    pub fn prefix_iter(&self, prefix: &str) -> AVLTreeSetRange<'_, String, P> {
+        self.prefix_range(prefix.as_bytes())
+    }
+
+    /// Counts the keys starting with prefix in O(log n).
+    pub fn count_prefix(&self, prefix: &str) -> usize {
+        self.prefix_iter(prefix).len()
+    }
+}
+
+impl<'a, P: BalancePolicy> AVLTreeSet<&'a str, P> {
+    /// Gets an iterator over the keys starting with prefix, in ascending order.
+    pub fn prefix_iter(&self, prefix: &str) -> AVLTreeSetRange<'_, &'a str, P> {
+        self.prefix_range(prefix.as_bytes())
+    }
+
+    /// Counts the keys starting with prefix in O(log n).
+    pub fn count_prefix(&self, prefix: &str) -> usize {
+        self.prefix_iter(prefix).len()
+    }
+}
+
+impl<P: BalancePolicy> AVLTreeSet<Vec<u8>, P> {
+    /// Gets an iterator over the keys starting with prefix, in ascending order.
+    pub fn prefix_iter(&self, prefix: &[u8]) -> AVLTreeSetRange<'_, Vec<u8>, P> {
+        self.prefix_range(prefix)
+    }
+
+    /// Counts the keys starting with prefix in O(log n).
+    pub fn count_prefix(&self, prefix: &[u8]) -> usize {
+        self.prefix_iter(prefix).len()
+    }
+}
+
+impl<T: Ord + AsRef<[u8]>, P: BalancePolicy> AVLTreeSet<T, P> {
+    /// Only correct for keys whose order is the order of their bytes.
+    fn prefix_range(&self, prefix: &[u8]) -> AVLTreeSetRange<'_, T, P> {
+        self.range_by(
+            |key| key.as_ref() < prefix,
+            |key| key.as_ref() < prefix || key.as_ref().starts_with(prefix),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AvlPolicy, TreapPolicy, WeightBalancedPolicy};
+
+    fn prefixes<P: BalancePolicy + Default>(keys: Vec<String>, prefix: String) -> bool {
+        let set = keys.iter().cloned().collect::<AVLTreeSet<_, P>>();
+        // Short prefixes of existing keys, as random ones rarely match anything
+        let prefixes = keys
+            .iter()
+            .flat_map(|key| key.char_indices().map(move |(i, _)| &key[..i]))
+            .chain([prefix.as_str()]);
+
+        prefixes.into_iter().all(|prefix| {
+            let expected = set.iter().filter(|key| key.starts_with(prefix)).collect::<Vec<_>>();
+
+            set.prefix_iter(prefix).eq(expected.iter().cloned())
+                && set.count_prefix(prefix) == expected.len()
+        })
+    }
+
+    #[quickcheck]
+    fn prefix_parity(keys: Vec<String>, prefix: String) -> bool {
+        prefixes::<AvlPolicy>(keys, prefix)
+    }
+
+    #[quickcheck]
+    fn weight_balanced_prefix_parity(keys: Vec<String>, prefix: String) -> bool {
+        prefixes::<WeightBalancedPolicy>(keys, prefix)
+    }
+
+    #[quickcheck]
+    fn treap_prefix_parity(keys: Vec<String>, prefix: String) -> bool {
+        prefixes::<TreapPolicy>(keys, prefix)
+    }
+
+    fn byte_prefixes<P: BalancePolicy + Default>(keys: Vec<Vec<u8>>, prefix: Vec<u8>) -> bool {
+        let set = keys.iter().cloned().collect::<AVLTreeSet<_, P>>();
+        let prefixes = keys
+            .iter()
+            .flat_map(|key| (0..=key.len()).map(move |i| &key[..i]))
+            .chain([prefix.as_slice()]);
+
+        prefixes.into_iter().all(|prefix| {
+            let expected = set.iter().filter(|key| key.starts_with(prefix)).collect::<Vec<_>>();
+
+            set.prefix_iter(prefix).rev().eq(expected.iter().rev().cloned())
+                && set.count_prefix(prefix) == expected.len()
+        })
+    }
+
+    #[quickcheck]
+    fn byte_prefix_parity(keys: Vec<Vec<u8>>, prefix: Vec<u8>) -> bool {
+        byte_prefixes::<AvlPolicy>(keys, prefix)
+    }
+
+    #[quickcheck]
+    fn weight_balanced_byte_prefix_parity(keys: Vec<Vec<u8>>, prefix: Vec<u8>) -> bool {
+        byte_prefixes::<WeightBalancedPolicy>(keys, prefix)
+    }
+
+    #[quickcheck]
+    fn treap_byte_prefix_parity(keys: Vec<Vec<u8>>, prefix: Vec<u8>) -> bool {
+        byte_prefixes::<TreapPolicy>(keys, prefix)
+    }
+
+    #[test]
+    fn identifiers() {
+        let set = ["foo", "foo.bar", "foobar", "fop", "fo", "bar", "foo\u{10ffff}"]
+            .into_iter()
+            .collect::<AVLTreeSet<&str>>();
+
+        assert_eq!(
+            set.prefix_iter("foo").collect::<Vec<_>>(),
+            [&"foo", &"foo.bar", &"foobar", &"foo\u{10ffff}"]
+        );
+        assert_eq!(set.count_prefix(""), 7);
+        assert_eq!(set.count_prefix("fo"), 6);
+        assert_eq!(set.count_prefix("x"), 0);
+
+        let bytes = [vec![0xff], vec![0xff, 0xff], vec![0xfe, 0xff], vec![]]
+            .into_iter()
+            .collect::<AVLTreeSet<Vec<u8>>>();
+        assert_eq!(bytes.count_prefix(&[0xff]), 2);
+        assert_eq!(bytes.count_prefix(&[]), 4);
+    }
+}
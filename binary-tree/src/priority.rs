@@ -0,0 +1,322 @@
+/*
+    Addressable priority queue.
+    Entries live in an AVLTreeSet ordered by (priority, key), and an
+    AVLTreeMap from key to priority finds an entry again, so a priority
+    can be changed or an entry removed in O(log n).
+*/
+
+use std::borrow::Borrow;
+use std::mem::replace;
+use std::panic::{catch_unwind, resume_unwind, AssertUnwindSafe};
+
+use crate::{AVLTreeMap, AVLTreeSet};
+
+#[derive(Debug, Clone)]
+/// A priority queue of unique keys whose priorities can be changed.
+///
+/// Entries with equal priorities are ordered by key.
+pub struct AVLPriorityQueue<K: Ord + Clone, P: Ord + Clone> {
+    queue: AVLTreeSet<(P, K)>,
+    priorities: AVLTreeMap<K, P>,
+}
+
+impl<K: Ord + Clone, P: Ord + Clone> Default for AVLPriorityQueue<K, P> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Ord + Clone, P: Ord + Clone> AVLPriorityQueue<K, P> {
+    /// Creates a new, empty AVLPriorityQueue.
+    pub fn new() -> Self {
+        Self {
+            queue: AVLTreeSet::new(),
+            priorities: AVLTreeMap::new(),
+        }
+    }
+
+    /// Returns the number of entries in the queue.
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Returns true if the queue contains no entries.
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Returns true if the queue contains an entry for key.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.priorities.contains_key(key)
+    }
+
+    /// Returns the priority of key, if it is in the queue.
+    pub fn priority<Q>(&self, key: &Q) -> Option<&P>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.priorities.get(key)
+    }
+
+    /// Adds key with priority. If key was already in the queue its priority is
+    /// changed instead, and the old priority is returned.
+    pub fn push(&mut self, key: K, priority: P) -> Option<P> {
+        if self.priorities.contains_key(&key) {
+            return self.change_priority(&key, priority);
+        }
+
+        self.queue.insert((priority.clone(), key.clone()));
+        self.priorities.insert(key, priority);
+        None
+    }
+
+    /// Returns the entry with the smallest priority, if any.
+    pub fn peek(&self) -> Option<(&K, &P)> {
+        self.queue.first().map(|(priority, key)| (key, priority))
+    }
+
+    /// Returns the entry with the largest priority, if any.
+    pub fn peek_max(&self) -> Option<(&K, &P)> {
+        self.queue.last().map(|(priority, key)| (key, priority))
+    }
+
+    /// Removes and returns the entry with the smallest priority, if any.
+    pub fn pop_min(&mut self) -> Option<(K, P)> {
+        let (priority, key) = self.queue.pop_first()?;
+        self.priorities.remove(&key);
+
+        Some((key, priority))
+    }
+
+    /// Removes and returns the entry with the largest priority, if any.
+    pub fn pop_max(&mut self) -> Option<(K, P)> {
+        let (priority, key) = self.queue.pop_last()?;
+        self.priorities.remove(&key);
+
+        Some((key, priority))
+    }
+
+    /// Changes the priority of key, returning the old one.
+    ///
+    /// Returns None and leaves the queue as it is if key is not in it.
+    /// If a comparison panics, the queue is left as it was, or without key
+    /// if the panic came while putting key back at its new priority.
+    pub fn change_priority<Q>(&mut self, key: &Q, priority: P) -> Option<P>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let current = self.priorities.get_mut(key)?;
+        let len = self.queue.len();
+        let mut taken = false;
+
+        let moved = catch_unwind(AssertUnwindSafe(|| {
+            let (_, owned) = self
+                .queue
+                .take_by(|(p, k)| p.cmp(current).then_with(|| k.borrow().cmp(key)))
+                .unwrap();
+            taken = true;
+            self.queue.insert((priority.clone(), owned));
+        }));
+
+        // A panic may also come from checking the invariants once the queue has changed,
+        // so its length tells whether the entry is gone, back at its new priority, or untouched
+        if let Err(payload) = moved {
+            if self.queue.len() < len {
+                self.priorities.remove(key);
+            } else if taken {
+                *current = priority;
+            }
+            resume_unwind(payload);
+        }
+
+        Some(replace(current, priority))
+    }
+
+    /// Removes key from the queue, returning its priority.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<P>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let priority = self.priorities.remove(key)?;
+        self.queue.take_by(|(p, k)| p.cmp(&priority).then_with(|| k.borrow().cmp(key)));
+
+        Some(priority)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::cmp::Ordering;
+    use std::collections::BTreeMap;
+
+    #[quickcheck]
+    fn queue_parity(ops: Vec<(u8, u8, u8)>) -> bool {
+        let mut queue = AVLPriorityQueue::new();
+        // Model from key to priority, the queue order found by sorting
+        let mut model = BTreeMap::new();
+        let model_min = |model: &BTreeMap<u8, u8>| model.iter().map(|(&k, &p)| (p, k)).min();
+        let model_max = |model: &BTreeMap<u8, u8>| model.iter().map(|(&k, &p)| (p, k)).max();
+
+        ops.into_iter().all(|(op, key, priority)| {
+            let key = key % 32;
+            let same = match op % 6 {
+                0 | 1 => queue.push(key, priority) == model.insert(key, priority),
+                2 => {
+                    let expected = model_min(&model).map(|(p, k)| (k, p));
+                    expected.inspect(|(k, _)| {
+                        model.remove(k);
+                    });
+                    queue.pop_min() == expected
+                }
+                3 => {
+                    let expected = model_max(&model).map(|(p, k)| (k, p));
+                    expected.inspect(|(k, _)| {
+                        model.remove(k);
+                    });
+                    queue.pop_max() == expected
+                }
+                4 => {
+                    let expected = model.get_mut(&key).map(|p| replace(p, priority));
+                    queue.change_priority(&key, priority) == expected
+                }
+                _ => queue.remove(&key) == model.remove(&key),
+            };
+
+            same && queue.len() == model.len()
+                && queue.peek().map(|(&k, &p)| (p, k)) == model_min(&model)
+                && queue.peek_max().map(|(&k, &p)| (p, k)) == model_max(&model)
+                && queue.queue.validate().is_ok()
+        })
+    }
+
+    thread_local! {
+        /// Comparisons left before the next one panics, if armed
+        static FUSE: Cell<Option<u8>> = const { Cell::new(None) };
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    /// A priority whose comparisons can be made to panic.
+    struct Fragile(u8);
+
+    impl PartialOrd for Fragile {
+        fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+            Some(self.cmp(other))
+        }
+    }
+
+    impl Ord for Fragile {
+        fn cmp(&self, other: &Self) -> Ordering {
+            FUSE.with(|fuse| match fuse.get() {
+                Some(0) => {
+                    fuse.set(None);
+                    resume_unwind(Box::new("fragile"))
+                }
+                Some(left) => fuse.set(Some(left - 1)),
+                None => {}
+            });
+            self.0.cmp(&other.0)
+        }
+    }
+
+    #[quickcheck]
+    fn panicking_ord_keeps_queue_consistent(xs: Vec<(u8, u8)>, key: u8, priority: u8, fuse: u8) -> bool {
+        let mut queue = AVLPriorityQueue::new();
+        for (k, p) in xs {
+            queue.push(k % 32, Fragile(p));
+        }
+
+        FUSE.set(Some(fuse % 16));
+        let _ = catch_unwind(AssertUnwindSafe(|| queue.change_priority(&(key % 32), Fragile(priority))));
+        FUSE.set(None);
+
+        // Every key in the map has its entry in the queue, so changing any priority works
+        let mut entries = queue.priorities.iter().map(|(key, priority)| (priority.clone(), *key)).collect::<Vec<_>>();
+        entries.sort();
+        let consistent = queue.queue.validate().is_ok() && queue.queue.iter().eq(entries.iter());
+        consistent && entries.iter().all(|(_, key)| queue.change_priority(key, Fragile(0)).is_some())
+    }
+
+    /// Shortest distances from node 0 with Dijkstra's algorithm, using change_priority as decrease-key.
+    fn dijkstra(nodes: usize, edges: &[(usize, usize, u32)]) -> Vec<Option<u32>> {
+        let mut distances = vec![None; nodes];
+        let mut queue = AVLPriorityQueue::new();
+        queue.push(0, 0);
+
+        while let Some((node, distance)) = queue.pop_min() {
+            distances[node] = Some(distance);
+
+            for &(_, to, weight) in edges.iter().filter(|&&(from, _, _)| from == node) {
+                let candidate = distance + weight;
+                if distances[to].is_some() {
+                    continue;
+                }
+                match queue.priority(&to) {
+                    Some(&current) if current <= candidate => {}
+                    Some(_) => {
+                        queue.change_priority(&to, candidate);
+                    }
+                    None => {
+                        queue.push(to, candidate);
+                    }
+                }
+            }
+        }
+
+        distances
+    }
+
+    /// Shortest distances from node 0 with Bellman-Ford.
+    fn bellman_ford(nodes: usize, edges: &[(usize, usize, u32)]) -> Vec<Option<u32>> {
+        let mut distances = vec![None; nodes];
+        distances[0] = Some(0);
+
+        for _ in 0..nodes {
+            for &(from, to, weight) in edges {
+                if let Some(distance) = distances[from] {
+                    let candidate = distance + weight;
+                    if distances[to].is_none_or(|current| candidate < current) {
+                        distances[to] = Some(candidate);
+                    }
+                }
+            }
+        }
+
+        distances
+    }
+
+    #[quickcheck]
+    fn dijkstra_matches_bellman_ford(edges: Vec<(u8, u8, u8)>) -> bool {
+        let nodes = 12;
+        let edges = edges
+            .into_iter()
+            .map(|(from, to, weight)| (from as usize % nodes, to as usize % nodes, weight as u32))
+            .collect::<Vec<_>>();
+
+        dijkstra(nodes, &edges) == bellman_ford(nodes, &edges)
+    }
+
+    #[test]
+    fn decrease_key() {
+        let mut queue = AVLPriorityQueue::new();
+        queue.push("a", 5);
+        queue.push("b", 3);
+        queue.push("c", 3);
+
+        assert_eq!(queue.peek(), Some((&"b", &3)));
+        assert_eq!(queue.change_priority("a", 1), Some(5));
+        assert_eq!(queue.change_priority("z", 1), None);
+        assert_eq!(queue.pop_min(), Some(("a", 1)));
+        assert_eq!(queue.remove("b"), Some(3));
+        assert_eq!(queue.pop_max(), Some(("c", 3)));
+        assert!(queue.is_empty());
+    }
+}
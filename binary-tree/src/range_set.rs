@@ -0,0 +1,254 @@
+/*
+    Set of values stored as disjoint half-open ranges.
+    An AVLTreeMap from each range's start to its end holds ranges that
+    neither overlap nor touch, so the range that could hold a value is the
+    one with the greatest start not after it. Inserting a range swallows
+    every range it meets and removing one trims or splits those it cuts,
+    in O((k + 1) log n) for k ranges affected.
+*/
+
+use std::borrow::Borrow;
+use std::iter;
+use std::ops::{Bound, Range};
+
+use crate::AVLTreeMap;
+
+#[derive(Debug, Clone)]
+/// A set of values stored as the fewest half-open ranges covering them.
+pub struct AVLRangeSet<T: Ord + Clone> {
+    /// Start to end of every range, no two overlapping or adjacent
+    ranges: AVLTreeMap<T, T>,
+}
+
+impl<T: Ord + Clone> Default for AVLRangeSet<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Ord + Clone> PartialEq for AVLRangeSet<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.ranges.iter().eq(other.ranges.iter())
+    }
+}
+
+impl<T: Ord + Clone> AVLRangeSet<T> {
+    /// Creates a new, empty AVLRangeSet.
+    pub fn new() -> Self {
+        Self {
+            ranges: AVLTreeMap::new(),
+        }
+    }
+
+    /// Returns the number of disjoint ranges in the set.
+    pub fn len(&self) -> usize {
+        self.ranges.len()
+    }
+
+    /// Returns true if the set contains no values.
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// Adds every value in range, merging it with the ranges it overlaps or touches.
+    ///
+    /// Returns true if any of the values was not in the set yet.
+    pub fn insert_range(&mut self, range: Range<T>) -> bool {
+        let Range { mut start, mut end } = range;
+        if start >= end {
+            return false;
+        }
+
+        if let Some((before_start, before_end)) = self.ranges.range(..=&start).next_back() {
+            if *before_end >= end {
+                return false;
+            }
+            if *before_end >= start {
+                start = before_start.clone();
+            }
+        }
+
+        // Every range starting from start up to and including end meets the new one
+        let met = self.ranges.range(&start..=&end).map(|(start, _)| start.clone()).collect::<Vec<_>>();
+        for met_start in met {
+            let met_end = self.ranges.remove(&met_start).unwrap();
+            if met_end > end {
+                end = met_end;
+            }
+        }
+
+        self.ranges.insert(start, end);
+        true
+    }
+
+    /// Removes every value in range, splitting the range holding it if needed.
+    ///
+    /// Returns true if any of the values was in the set.
+    pub fn remove_range(&mut self, range: Range<T>) -> bool {
+        let Range { start, end } = range;
+        if start >= end {
+            return false;
+        }
+
+        let mut removed = false;
+
+        // A range starting before start may reach into the removed part, and even past it
+        let before = self.ranges.range(..&start).next_back();
+        if let Some(before_start) = before.filter(|(_, before_end)| **before_end > start).map(|(s, _)| s.clone()) {
+            let before_end = self.ranges.insert(before_start, start.clone()).unwrap();
+            if before_end > end {
+                self.ranges.insert(end.clone(), before_end);
+            }
+            removed = true;
+        }
+
+        let inside = self.ranges.range(&start..&end).map(|(start, _)| start.clone()).collect::<Vec<_>>();
+        for inside_start in inside {
+            let inside_end = self.ranges.remove(&inside_start).unwrap();
+            if inside_end > end {
+                self.ranges.insert(end.clone(), inside_end);
+            }
+            removed = true;
+        }
+
+        removed
+    }
+
+    /// Returns true if the set contains the value.
+    pub fn contains<Q>(&self, value: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.ranges
+            .range((Bound::Unbounded, Bound::Included(value)))
+            .next_back()
+            .is_some_and(|(_, end)| end.borrow() > value)
+    }
+
+    /// Gets an iterator that visits the ranges of the set in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = Range<T>> + '_ {
+        self.ranges.iter().map(|(start, end)| start.clone()..end.clone())
+    }
+
+    /// Gets an iterator over the parts of within not in the set, as ranges in ascending order.
+    pub fn gaps(&self, within: Range<T>) -> impl Iterator<Item = Range<T>> + '_ {
+        let Range { start, end } = within;
+
+        // Skip the part of within covered by a range starting before it
+        let mut cursor = match self.ranges.range(..=&start).next_back() {
+            Some((_, before_end)) if *before_end > start => before_end.clone(),
+            _ => start.clone(),
+        };
+        let mut inside = self
+            .ranges
+            .range((Bound::Included(start), Bound::Excluded(end.clone())));
+
+        iter::from_fn(move || {
+            while cursor < end {
+                match inside.next() {
+                    Some((next_start, next_end)) if *next_start > cursor => {
+                        let gap = cursor.clone()..next_start.clone();
+                        cursor = next_end.clone();
+                        return Some(gap);
+                    }
+                    Some((_, next_end)) => cursor = next_end.clone(),
+                    None => {
+                        let gap = cursor.clone()..end.clone();
+                        cursor = end.clone();
+                        return Some(gap);
+                    }
+                }
+            }
+
+            None
+        })
+    }
+
+    /// Returns the set of values in within that are not in this set.
+    pub fn complement(&self, within: Range<T>) -> Self {
+        self.gaps(within).collect()
+    }
+}
+
+impl<T: Ord + Clone> FromIterator<Range<T>> for AVLRangeSet<T> {
+    /// Create an AVLRangeSet holding every value of every range.
+    fn from_iter<I: IntoIterator<Item = Range<T>>>(iter: I) -> Self {
+        let mut set = Self::new();
+
+        for range in iter {
+            set.insert_range(range);
+        }
+
+        set
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    /// Values of within in model, as the fewest ranges.
+    fn runs(model: &BTreeSet<u8>, within: Range<u8>) -> Vec<Range<u8>> {
+        let mut runs = Vec::<Range<u8>>::new();
+        for value in within.filter(|value| model.contains(value)) {
+            match runs.last_mut() {
+                Some(run) if run.end == value => run.end += 1,
+                _ => runs.push(value..value + 1),
+            }
+        }
+        runs
+    }
+
+    #[quickcheck]
+    fn range_set_parity(ops: Vec<(bool, u8, u8)>, a: u8, b: u8) -> bool {
+        let mut set = AVLRangeSet::new();
+        let mut model = BTreeSet::new();
+        let within = a % 72..b % 72;
+
+        ops.into_iter().all(|(insert, start, end)| {
+            let range = start % 64..end % 64;
+            let changed = if insert {
+                let added = range.clone().filter(|&value| model.insert(value)).count() > 0;
+                set.insert_range(range) == added
+            } else {
+                let removed = range.clone().filter(|value| model.remove(value)).count() > 0;
+                set.remove_range(range) == removed
+            };
+
+            let uncovered = within.clone().filter(|value| !model.contains(value)).collect::<BTreeSet<_>>();
+
+            changed
+                && set.iter().eq(runs(&model, 0..72))
+                && (0..72).all(|value| set.contains(&value) == model.contains(&value))
+                && set.gaps(within.clone()).eq(runs(&uncovered, within.clone()))
+                && set.complement(within.clone()).iter().eq(runs(&uncovered, within.clone()))
+        })
+    }
+
+    #[test]
+    fn allocated_ports() {
+        let mut ports = AVLRangeSet::new();
+        assert!(ports.insert_range(8000..8010));
+        assert!(ports.insert_range(8010..8020));
+        assert!(ports.insert_range(9000..9100));
+        assert!(!ports.insert_range(8005..8015));
+
+        // Touching ranges were merged into one
+        assert_eq!(ports.iter().collect::<Vec<_>>(), [8000..8020, 9000..9100]);
+
+        assert!(ports.remove_range(8050..9050));
+        assert!(ports.remove_range(8010..8012));
+        assert!(!ports.remove_range(8500..8600));
+        assert_eq!(ports.iter().collect::<Vec<_>>(), [8000..8010, 8012..8020, 9050..9100]);
+        assert!(ports.contains(&8013) && !ports.contains(&8011) && !ports.contains(&8020));
+
+        assert_eq!(ports.gaps(8000..9060).collect::<Vec<_>>(), [8010..8012, 8020..9050]);
+
+        let free = ports.complement(7990..8005);
+        assert_eq!(free.len(), 1);
+        assert!(free.contains(&7990) && free.contains(&7999) && !free.contains(&8000));
+        assert!(AVLRangeSet::<u16>::new().gaps(5..5).next().is_none());
+    }
+}
@@ -0,0 +1,565 @@
+/*
+    Rope text buffer on an AVL Tree.
+    Every node holds a chunk of text and caches the byte, char and line counts
+    of its subtree, so positions are found by walking down from the root.
+*/
+
+use std::fmt;
+use std::ops::{Bound, RangeBounds};
+
+use crate::ops::{build, join, join2};
+use crate::{AVLNode, AVLTree, AVLTreeSetNodeIter, Augment};
+
+/// Largest chunk in bytes. Chunks are only grown in place up to this size.
+#[cfg(not(test))]
+const MAX_CHUNK_BYTES: usize = 1024;
+/// Tiny chunks in tests, so short strings still span many nodes.
+#[cfg(test)]
+const MAX_CHUNK_BYTES: usize = 8;
+
+#[derive(Debug, PartialEq, Clone)]
+/// A piece of the text together with its own counts.
+struct Chunk {
+    text: String,
+    chars: usize,
+    lines: usize,
+}
+
+impl Chunk {
+    fn new(text: String) -> Self {
+        let mut chunk = Self {
+            text,
+            chars: 0,
+            lines: 0,
+        };
+        chunk.recount();
+        chunk
+    }
+
+    fn recount(&mut self) {
+        self.chars = self.text.chars().count();
+        self.lines = self.text.matches('\n').count();
+    }
+
+    /// Byte offset of the char at char_idx, or the length if char_idx is the end.
+    fn byte_offset(&self, char_idx: usize) -> usize {
+        self.text
+            .char_indices()
+            .nth(char_idx)
+            .map_or(self.text.len(), |(offset, _)| offset)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+/// Counts for a whole subtree. Lines are counted as line breaks.
+struct TextSummary {
+    bytes: usize,
+    chars: usize,
+    lines: usize,
+}
+
+impl Augment<Chunk> for TextSummary {
+    fn compute(value: &Chunk, left: Option<&Self>, right: Option<&Self>) -> Self {
+        let left = left.copied().unwrap_or_default();
+        let right = right.copied().unwrap_or_default();
+
+        Self {
+            bytes: left.bytes + value.text.len() + right.bytes,
+            chars: left.chars + value.chars + right.chars,
+            lines: left.lines + value.lines + right.lines,
+        }
+    }
+}
+
+type RopeTree = AVLTree<Chunk, TextSummary>;
+
+#[derive(Debug, PartialEq, Clone, Default)]
+/// A text buffer for cheap edits in large texts.
+///
+/// All positions are char indices. Lines are separated by `\n` and a line
+/// includes its line break, so a text always has one more line than line breaks.
+pub struct Rope {
+    root: RopeTree,
+}
+
+impl Rope {
+    /// Creates a new, empty Rope.
+    pub fn new() -> Self {
+        Self { root: None }
+    }
+
+    /// Returns the length of the text in bytes.
+    pub fn len_bytes(&self) -> usize {
+        summary(&self.root).bytes
+    }
+
+    /// Returns the length of the text in chars.
+    pub fn len_chars(&self) -> usize {
+        summary(&self.root).chars
+    }
+
+    /// Returns the number of lines in the text.
+    pub fn len_lines(&self) -> usize {
+        summary(&self.root).lines + 1
+    }
+
+    /// Returns true if the text is empty.
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    /// Inserts text at char_idx.
+    ///
+    /// Panics if char_idx > len_chars.
+    pub fn insert(&mut self, char_idx: usize, text: &str) {
+        let len = self.len_chars();
+        if char_idx > len {
+            panic!("char index (is {}) should be <= len (is {})", char_idx, len);
+        }
+
+        if text.is_empty() || insert_in_chunk(&mut self.root, char_idx, text) {
+            return;
+        }
+
+        let (left, right) = split_chars(self.root.take(), char_idx);
+        let middle = build(chunks(text));
+        self.root = join2(join2(left, middle), right);
+    }
+
+    /// Removes the chars in range.
+    ///
+    /// Panics if the range is out of bounds.
+    pub fn remove<R: RangeBounds<usize>>(&mut self, range: R) {
+        let (start, end) = self.char_range(range);
+        if start == end || remove_in_chunk(&mut self.root, start, end) {
+            return;
+        }
+
+        let (left, rest) = split_chars(self.root.take(), start);
+        let (_, right) = split_chars(rest, end - start);
+        self.root = join2(left, right);
+    }
+
+    /// Returns the text of the chars in range.
+    ///
+    /// Panics if the range is out of bounds.
+    pub fn slice<R: RangeBounds<usize>>(&self, range: R) -> String {
+        let (start, end) = self.char_range(range);
+        let mut text = String::new();
+        collect_chars(&self.root, start, end, &mut text);
+        text
+    }
+
+    /// Returns line number line_idx, including its line break if it has one.
+    ///
+    /// Panics if line_idx >= len_lines.
+    pub fn line(&self, line_idx: usize) -> String {
+        let start = self.line_to_char(line_idx);
+        let end = if line_idx + 1 < self.len_lines() {
+            self.line_to_char(line_idx + 1)
+        } else {
+            self.len_chars()
+        };
+
+        self.slice(start..end)
+    }
+
+    /// Returns the line that the char at char_idx is on.
+    ///
+    /// Panics if char_idx > len_chars.
+    pub fn char_to_line(&self, char_idx: usize) -> usize {
+        let len = self.len_chars();
+        if char_idx > len {
+            panic!("char index (is {}) should be <= len (is {})", char_idx, len);
+        }
+
+        line_breaks_before(&self.root, char_idx)
+    }
+
+    /// Returns the char index of the first char on line line_idx.
+    ///
+    /// Panics if line_idx >= len_lines.
+    pub fn line_to_char(&self, line_idx: usize) -> usize {
+        let len = self.len_lines();
+        if line_idx >= len {
+            panic!("line index (is {}) should be < len (is {})", line_idx, len);
+        }
+
+        if line_idx == 0 {
+            0
+        } else {
+            char_after_line_break(&self.root, line_idx)
+        }
+    }
+
+    /// Gets an iterator over the chunks of text in order.
+    pub fn chunks(&self) -> impl Iterator<Item = &'_ str> + '_ {
+        AVLTreeSetNodeIter {
+            prev_nodes: Vec::default(),
+            current_tree: &self.root,
+        }
+        .map(|_node| _node.value.text.as_str())
+    }
+
+    /// Resolves a range of chars to start and end indices.
+    fn char_range<R: RangeBounds<usize>>(&self, range: R) -> (usize, usize) {
+        let len = self.len_chars();
+        let start = match range.start_bound() {
+            Bound::Included(&start) => start,
+            Bound::Excluded(&start) => start + 1,
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&end) => end + 1,
+            Bound::Excluded(&end) => end,
+            Bound::Unbounded => len,
+        };
+
+        if start > end || end > len {
+            panic!("char range {}..{} out of bounds for len {}", start, end, len);
+        }
+
+        (start, end)
+    }
+}
+
+impl From<&str> for Rope {
+    fn from(text: &str) -> Self {
+        Self {
+            root: build(chunks(text)),
+        }
+    }
+}
+
+impl fmt::Display for Rope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for chunk in self.chunks() {
+            f.write_str(chunk)?;
+        }
+        Ok(())
+    }
+}
+
+/// Summary of a subtree, all zero if empty.
+fn summary(tree: &RopeTree) -> TextSummary {
+    tree.as_ref().map_or(TextSummary::default(), |node| node.summary)
+}
+
+/// Cuts text into chunks of at most MAX_CHUNK_BYTES on char boundaries.
+fn chunks(text: &str) -> Vec<Chunk> {
+    let mut chunks = Vec::new();
+    let mut rest = text;
+
+    while !rest.is_empty() {
+        let mut end = rest.len().min(MAX_CHUNK_BYTES);
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+
+        chunks.push(Chunk::new(rest[..end].to_owned()));
+        rest = &rest[end..];
+    }
+
+    chunks
+}
+
+/// Inserts text directly into the chunk at char_idx if it has room.
+/// Returns false if no chunk could take the text.
+fn insert_in_chunk(tree: &mut RopeTree, char_idx: usize, text: &str) -> bool {
+    let current_node = match tree {
+        Some(current_node) => current_node,
+        None => return false,
+    };
+
+    let left_chars = summary(&current_node.left).chars;
+    let chunk_chars = current_node.value.chars;
+
+    let inserted = if char_idx < left_chars {
+        insert_in_chunk(&mut current_node.left, char_idx, text)
+    } else if char_idx <= left_chars + chunk_chars {
+        let chunk = &mut current_node.value;
+        if chunk.text.len() + text.len() > MAX_CHUNK_BYTES {
+            return false;
+        }
+
+        let offset = chunk.byte_offset(char_idx - left_chars);
+        chunk.text.insert_str(offset, text);
+        chunk.recount();
+        true
+    } else {
+        insert_in_chunk(&mut current_node.right, char_idx - left_chars - chunk_chars, text)
+    };
+
+    // Only counts changed, the shape of the tree is the same
+    if inserted {
+        current_node.update_height();
+    }
+
+    inserted
+}
+
+/// Removes the chars [start, end) directly from a chunk if they all lie inside it
+/// and the chunk is left non-empty. Returns false otherwise.
+fn remove_in_chunk(tree: &mut RopeTree, start: usize, end: usize) -> bool {
+    let current_node = match tree {
+        Some(current_node) => current_node,
+        None => return false,
+    };
+
+    let left_chars = summary(&current_node.left).chars;
+    let chunk_chars = current_node.value.chars;
+
+    let removed = if end <= left_chars {
+        remove_in_chunk(&mut current_node.left, start, end)
+    } else if start >= left_chars + chunk_chars {
+        let offset = left_chars + chunk_chars;
+        remove_in_chunk(&mut current_node.right, start - offset, end - offset)
+    } else if start >= left_chars && end <= left_chars + chunk_chars && end - start < chunk_chars {
+        let chunk = &mut current_node.value;
+        let start_byte = chunk.byte_offset(start - left_chars);
+        let end_byte = chunk.byte_offset(end - left_chars);
+        chunk.text.replace_range(start_byte..end_byte, "");
+        chunk.recount();
+        true
+    } else {
+        false
+    };
+
+    if removed {
+        current_node.update_height();
+    }
+
+    removed
+}
+
+/// Splits a tree into the first char_idx chars and the rest, cutting a chunk in two if needed.
+fn split_chars(tree: RopeTree, char_idx: usize) -> (RopeTree, RopeTree) {
+    let mut node = match tree {
+        Some(node) => node,
+        None => return (None, None),
+    };
+
+    let left = node.left.take();
+    let right = node.right.take();
+    let left_chars = summary(&left).chars;
+    let chunk_chars = node.value.chars;
+
+    if char_idx <= left_chars {
+        let (left_left, left_right) = split_chars(left, char_idx);
+        (left_left, Some(join(left_right, node, right)))
+    } else if char_idx >= left_chars + chunk_chars {
+        let (right_left, right_right) = split_chars(right, char_idx - left_chars - chunk_chars);
+        (Some(join(left, node, right_left)), right_right)
+    } else {
+        // The split point is inside this chunk
+        let offset = node.value.byte_offset(char_idx - left_chars);
+        let tail = node.value.text.split_off(offset);
+        node.value.recount();
+        let tail_node = Box::new(AVLNode::new(Chunk::new(tail)));
+
+        (Some(join(left, node, None)), Some(join(None, tail_node, right)))
+    }
+}
+
+/// Appends the chars [start, end) of a subtree to text.
+fn collect_chars(tree: &RopeTree, start: usize, end: usize, text: &mut String) {
+    let current_node = match tree {
+        Some(current_node) if start < end => current_node,
+        _ => return,
+    };
+
+    let left_chars = summary(&current_node.left).chars;
+    let chunk = &current_node.value;
+    let chunk_end = left_chars + chunk.chars;
+
+    if start < left_chars {
+        collect_chars(&current_node.left, start, end.min(left_chars), text);
+    }
+
+    if start < chunk_end && end > left_chars {
+        let from = chunk.byte_offset(start.saturating_sub(left_chars));
+        let to = chunk.byte_offset(end.min(chunk_end) - left_chars);
+        text.push_str(&chunk.text[from..to]);
+    }
+
+    if end > chunk_end {
+        collect_chars(&current_node.right, start.saturating_sub(chunk_end), end - chunk_end, text);
+    }
+}
+
+/// Counts the line breaks among the first char_idx chars of a subtree.
+fn line_breaks_before(tree: &RopeTree, char_idx: usize) -> usize {
+    let current_node = match tree {
+        Some(current_node) => current_node,
+        None => return 0,
+    };
+
+    let left = summary(&current_node.left);
+    let chunk = &current_node.value;
+
+    if char_idx <= left.chars {
+        line_breaks_before(&current_node.left, char_idx)
+    } else if char_idx <= left.chars + chunk.chars {
+        let in_chunk = chunk
+            .text
+            .chars()
+            .take(char_idx - left.chars)
+            .filter(|&c| c == '\n')
+            .count();
+        left.lines + in_chunk
+    } else {
+        left.lines + chunk.lines + line_breaks_before(&current_node.right, char_idx - left.chars - chunk.chars)
+    }
+}
+
+/// Char index just after line break number line_idx (counted from 1) of a subtree.
+fn char_after_line_break(tree: &RopeTree, line_idx: usize) -> usize {
+    let current_node = tree.as_ref().unwrap();
+    let left = summary(&current_node.left);
+    let chunk = &current_node.value;
+
+    if line_idx <= left.lines {
+        char_after_line_break(&current_node.left, line_idx)
+    } else if line_idx <= left.lines + chunk.lines {
+        let in_chunk = chunk
+            .text
+            .chars()
+            .enumerate()
+            .filter(|&(_, c)| c == '\n')
+            .nth(line_idx - left.lines - 1)
+            .map(|(i, _)| i)
+            .unwrap();
+        left.chars + in_chunk + 1
+    } else {
+        left.chars + chunk.chars + char_after_line_break(&current_node.right, line_idx - left.lines - chunk.lines)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cmp::max;
+
+    /// Checks heights, sizes, balance and cached counts of every node
+    fn is_valid(rope: &Rope) -> bool {
+        AVLTreeSetNodeIter {
+            prev_nodes: Vec::default(),
+            current_tree: &rope.root,
+        }
+        .all(|_node| {
+            let summary = TextSummary::compute(
+                &_node.value,
+                _node.left.as_ref().map(|node| &node.summary),
+                _node.right.as_ref().map(|node| &node.summary),
+            );
+
+            !_node.value.text.is_empty()
+                && _node.summary == summary
+                && _node.height == 1 + max(_node.left_height(), _node.right_height())
+                && _node.size == 1 + _node.left_size() + _node.right_size()
+                && _node.balance_factor().abs() < 2
+        })
+    }
+
+    /// Byte offset of char_idx in text
+    fn byte_offset(text: &str, char_idx: usize) -> usize {
+        text.char_indices().nth(char_idx).map_or(text.len(), |(offset, _)| offset)
+    }
+
+    /// Line line_idx of text, including its line break
+    fn line(text: &str, line_idx: usize) -> String {
+        let mut line = text.split('\n').nth(line_idx).unwrap().to_owned();
+        if line_idx < text.matches('\n').count() {
+            line.push('\n');
+        }
+        line
+    }
+
+    /// Applies edits to both a Rope and a String.
+    /// An edit with text inserts it, an edit without removes a range.
+    fn edit(initial: &str, edits: Vec<(usize, usize, Option<String>)>) -> (Rope, String) {
+        let mut rope = Rope::from(initial);
+        let mut string = initial.to_owned();
+
+        for (a, b, text) in edits {
+            let len = string.chars().count();
+            match text {
+                Some(text) => {
+                    let index = a % (len + 1);
+                    rope.insert(index, &text);
+                    string.insert_str(byte_offset(&string, index), &text);
+                }
+                None => {
+                    let start = a % (len + 1);
+                    let end = start + b % (len - start + 1);
+                    rope.remove(start..end);
+                    string.replace_range(byte_offset(&string, start)..byte_offset(&string, end), "");
+                }
+            }
+        }
+
+        (rope, string)
+    }
+
+    #[quickcheck]
+    fn edit_parity(initial: String, edits: Vec<(usize, usize, Option<String>)>) -> bool {
+        let (rope, string) = edit(&initial, edits);
+
+        is_valid(&rope)
+            && rope.to_string() == string
+            && rope.len_bytes() == string.len()
+            && rope.len_chars() == string.chars().count()
+            && rope.len_lines() == string.matches('\n').count() + 1
+    }
+
+    #[quickcheck]
+    fn slice_parity(initial: String, edits: Vec<(usize, usize, Option<String>)>, a: usize, b: usize) -> bool {
+        let (rope, string) = edit(&initial, edits);
+        let len = string.chars().count();
+        let start = a % (len + 1);
+        let end = start + b % (len - start + 1);
+
+        rope.slice(start..end) == string[byte_offset(&string, start)..byte_offset(&string, end)]
+    }
+
+    #[quickcheck]
+    fn line_parity(initial: String, edits: Vec<(usize, usize, Option<String>)>) -> bool {
+        let (rope, string) = edit(&initial, edits);
+
+        (0..rope.len_lines()).all(|n| rope.line(n) == line(&string, n))
+    }
+
+    #[quickcheck]
+    fn char_line_conversion_parity(initial: String, edits: Vec<(usize, usize, Option<String>)>) -> bool {
+        let (rope, string) = edit(&initial, edits);
+        let chars = string.chars().collect::<Vec<_>>();
+
+        let char_to_line = (0..=chars.len())
+            .all(|i| rope.char_to_line(i) == chars[..i].iter().filter(|&&c| c == '\n').count());
+        let line_to_char = (0..rope.len_lines())
+            .all(|n| rope.char_to_line(rope.line_to_char(n)) == n
+                && (n == 0 || chars[rope.line_to_char(n) - 1] == '\n'));
+
+        char_to_line && line_to_char
+    }
+
+    #[test]
+    fn lines() {
+        let mut rope = Rope::from("first\nsecond\n");
+        rope.insert(6, "inserted ");
+
+        assert_eq!(rope.len_lines(), 3);
+        assert_eq!(rope.line(0), "first\n");
+        assert_eq!(rope.line(1), "inserted second\n");
+        assert_eq!(rope.line(2), "");
+        assert_eq!(rope.line_to_char(1), 6);
+        assert_eq!(rope.char_to_line(6), 1);
+    }
+
+    #[test]
+    #[should_panic]
+    fn remove_out_of_bounds() {
+        let mut rope = Rope::from("text");
+        rope.remove(2..5);
+    }
+}
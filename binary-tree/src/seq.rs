@@ -0,0 +1,284 @@
+/*
+    Implicit key AVL Tree.
+    Positions come from subtree sizes instead of comparing values,
+    which gives a list with O(log n) indexing, insertion and removal.
+*/
+
+use std::cmp::Ordering;
+use std::mem::replace;
+
+use crate::ops::{build, get_at, get_at_mut, join2, split_at, split_first};
+use crate::{AVLNode, AVLTree, AVLTreeSetNodeIter};
+
+#[derive(Debug, PartialEq, Clone)]
+/// A sequence based on an implicit key AVL Tree.
+pub struct AVLSeq<T> {
+    root: AVLTree<T>,
+}
+
+impl<T> Default for AVLSeq<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> AVLSeq<T> {
+    /// Creates a new, empty AVLSeq.
+    ///
+    /// Does not allocate anything on its own.
+    pub fn new() -> Self {
+        Self { root: None }
+    }
+
+    /// Returns the number of elements in the sequence.
+    pub fn len(&self) -> usize {
+        self.root.as_ref().map_or(0, |node| node.size)
+    }
+
+    /// Returns true if the sequence contains no elements.
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    /// Returns a reference to the element at index, or None if out of bounds.
+    pub fn get(&self, index: usize) -> Option<&T> {
+        get_at(&self.root, index).map(|node| &node.value)
+    }
+
+    /// Returns a mutable reference to the element at index, or None if out of bounds.
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        get_at_mut(&mut self.root, index).map(|node| &mut node.value)
+    }
+
+    /// Inserts an element at index, shifting all elements after it to the right.
+    ///
+    /// Panics if index > len.
+    pub fn insert(&mut self, index: usize, value: T) {
+        let len = self.len();
+        if index > len {
+            panic!("insertion index (is {}) should be <= len (is {})", index, len);
+        }
+
+        insert_at(&mut self.root, index, value);
+    }
+
+    /// Appends an element to the back of the sequence.
+    pub fn push(&mut self, value: T) {
+        let len = self.len();
+        self.insert(len, value);
+    }
+
+    /// Removes and returns the element at index, shifting all elements after it to the left.
+    ///
+    /// Panics if index is out of bounds.
+    pub fn remove(&mut self, index: usize) -> T {
+        let len = self.len();
+        if index >= len {
+            panic!("removal index (is {}) should be < len (is {})", index, len);
+        }
+
+        remove_at(&mut self.root, index)
+    }
+
+    /// Splits the sequence in two at index.
+    ///
+    /// The first sequence holds the elements [0, index) and the second the elements [index, len).
+    ///
+    /// Panics if index > len.
+    pub fn split_at(self, index: usize) -> (Self, Self) {
+        let len = self.len();
+        if index > len {
+            panic!("split index (is {}) should be <= len (is {})", index, len);
+        }
+
+        let (left, right) = split_at(self.root, index);
+        (Self { root: left }, Self { root: right })
+    }
+
+    /// Concatenates two sequences, placing the elements of other after the elements of self.
+    pub fn concat(self, other: Self) -> Self {
+        Self {
+            root: join2(self.root, other.root),
+        }
+    }
+
+    /// Gets an iterator that visits the elements in sequence order.
+    pub fn iter(&self) -> impl Iterator<Item = &'_ T> + '_ {
+        AVLTreeSetNodeIter {
+            prev_nodes: Vec::default(),
+            current_tree: &self.root,
+        }
+        .map(|_node| &_node.value)
+    }
+}
+
+/// Inserts a value at index within a subtree and rebalances on the way back up.
+fn insert_at<T>(tree: &mut AVLTree<T>, index: usize, value: T) {
+    let current_node = match tree {
+        Some(current_node) => current_node,
+        None => {
+            *tree = Some(Box::new(AVLNode::new(value)));
+            return;
+        }
+    };
+
+    let left_size = current_node.left_size();
+    if index <= left_size {
+        insert_at(&mut current_node.left, index, value);
+    } else {
+        insert_at(&mut current_node.right, index - left_size - 1, value);
+    }
+
+    current_node.update_height();
+    current_node.rebalance();
+}
+
+/// Removes the value at index within a subtree and rebalances on the way back up.
+fn remove_at<T>(tree: &mut AVLTree<T>, index: usize) -> T {
+    let current_node = tree.as_mut().unwrap();
+    let left_size = current_node.left_size();
+
+    let value = match index.cmp(&left_size) {
+        Ordering::Less => remove_at(&mut current_node.left, index),
+        Ordering::Greater => remove_at(&mut current_node.right, index - left_size - 1),
+        Ordering::Equal => match current_node.right.take() {
+            // No right child, replace the node with its left child
+            None => {
+                let left = current_node.left.take();
+                return replace(tree, left).unwrap().value;
+            }
+            // Replace the value with the first value of the right side
+            Some(right) => {
+                let (rest, first) = split_first(right);
+                current_node.right = rest;
+                replace(&mut current_node.value, first.value)
+            }
+        },
+    };
+
+    current_node.update_height();
+    current_node.rebalance();
+
+    value
+}
+
+impl<T> FromIterator<T> for AVLSeq<T> {
+    /// Create an AVLSeq from an iterator, keeping the iteration order.
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        Self {
+            root: build(iter.into_iter().collect()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck::{Arbitrary, Gen};
+    use std::cmp::max;
+
+    impl<T: Arbitrary> Arbitrary for AVLSeq<T> {
+        fn arbitrary(g: &mut Gen) -> Self {
+            let vec: Vec<T> = Arbitrary::arbitrary(g);
+            vec.into_iter().collect()
+        }
+
+        fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+            let vec: Vec<T> = self.iter().cloned().collect();
+            Box::new(vec.shrink().map(|v| v.into_iter().collect::<Self>()))
+        }
+    }
+
+    /// Checks heights, sizes and balance of every node
+    fn is_balanced<T>(seq: &AVLSeq<T>) -> bool {
+        AVLTreeSetNodeIter {
+            prev_nodes: Vec::default(),
+            current_tree: &seq.root,
+        }
+        .all(|_node| {
+            _node.height == 1 + max(_node.left_height(), _node.right_height())
+                && _node.size == 1 + _node.left_size() + _node.right_size()
+                && _node.balance_factor().abs() < 2
+        })
+    }
+
+    #[quickcheck]
+    fn insert_parity(ops: Vec<(usize, i32)>) -> bool {
+        let mut seq = AVLSeq::new();
+        let mut vec = Vec::new();
+
+        for (index, value) in ops {
+            let index = index % (vec.len() + 1);
+            seq.insert(index, value);
+            vec.insert(index, value);
+        }
+
+        is_balanced(&seq) && seq.len() == vec.len() && seq.iter().eq(vec.iter())
+    }
+
+    #[quickcheck]
+    fn remove_parity(xs: Vec<i32>, indices: Vec<usize>) -> bool {
+        let mut seq = xs.iter().cloned().collect::<AVLSeq<_>>();
+        let mut vec = xs;
+
+        for index in indices {
+            if vec.is_empty() {
+                break;
+            }
+            let index = index % vec.len();
+            if seq.remove(index) != vec.remove(index) {
+                return false;
+            }
+        }
+
+        is_balanced(&seq) && seq.iter().eq(vec.iter())
+    }
+
+    #[quickcheck]
+    fn get_parity(xs: Vec<i32>, index: usize) -> bool {
+        let seq = xs.iter().cloned().collect::<AVLSeq<_>>();
+        let index = index % (xs.len() + 2);
+
+        seq.get(index) == xs.get(index)
+    }
+
+    #[quickcheck]
+    fn split_at_parity(seq: AVLSeq<i32>, index: usize) -> bool {
+        let vec = seq.iter().cloned().collect::<Vec<_>>();
+        let index = index % (vec.len() + 1);
+
+        let (left, right) = seq.split_at(index);
+        let (vec_left, vec_right) = vec.split_at(index);
+
+        is_balanced(&left)
+            && is_balanced(&right)
+            && left.iter().eq(vec_left.iter())
+            && right.iter().eq(vec_right.iter())
+    }
+
+    #[quickcheck]
+    fn concat_parity(first: AVLSeq<i32>, second: AVLSeq<i32>) -> bool {
+        let mut vec = first.iter().cloned().collect::<Vec<_>>();
+        vec.extend(second.iter().cloned());
+
+        let seq = first.concat(second);
+
+        is_balanced(&seq) && seq.len() == vec.len() && seq.iter().eq(vec.iter())
+    }
+
+    #[test]
+    fn get_mut_updates_element() {
+        let mut seq = (0..10).collect::<AVLSeq<_>>();
+        *seq.get_mut(4).unwrap() = 40;
+
+        assert_eq!(seq.get(4), Some(&40));
+        assert_eq!(seq.get_mut(10), None);
+    }
+
+    #[test]
+    #[should_panic]
+    fn insert_out_of_bounds() {
+        let mut seq = AVLSeq::new();
+        seq.insert(1, 0);
+    }
+}
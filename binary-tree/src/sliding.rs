@@ -0,0 +1,169 @@
+/*
+    Rolling quantiles over the last N samples.
+    Samples are kept twice: in arrival order to know which to evict, and in
+    an AVLTreeSet tagged with their arrival number so equal samples stay
+    distinct. Subtree sizes then find the sample at any rank in O(log N).
+*/
+
+use std::collections::VecDeque;
+
+use crate::ops::get_at;
+use crate::AVLTreeSet;
+
+#[derive(Debug, Clone)]
+/// Median, quantiles and largest values of a sliding window of samples.
+pub struct SlidingQuantile<T: Ord + Clone> {
+    /// Samples with their arrival number
+    sorted: AVLTreeSet<(T, u64)>,
+    /// Samples oldest first
+    arrivals: VecDeque<T>,
+    /// Arrival number of the oldest sample
+    oldest: u64,
+    window: usize,
+}
+
+impl<T: Ord + Clone> SlidingQuantile<T> {
+    /// Creates a tracker over the last window samples.
+    pub fn new(window: usize) -> Self {
+        Self {
+            sorted: AVLTreeSet::new(),
+            arrivals: VecDeque::new(),
+            oldest: 0,
+            window,
+        }
+    }
+
+    /// Returns the number of samples in the window.
+    pub fn len(&self) -> usize {
+        self.arrivals.len()
+    }
+
+    /// Returns true if the window holds no samples.
+    pub fn is_empty(&self) -> bool {
+        self.arrivals.is_empty()
+    }
+
+    /// Adds a sample. If the window was full the oldest sample is evicted and returned.
+    pub fn push(&mut self, sample: T) -> Option<T> {
+        if self.window == 0 {
+            return Some(sample);
+        }
+
+        let evicted = if self.len() == self.window {
+            self.evict_oldest()
+        } else {
+            None
+        };
+
+        let arrival = self.oldest + self.arrivals.len() as u64;
+        self.sorted.insert((sample.clone(), arrival));
+        self.arrivals.push_back(sample);
+
+        evicted
+    }
+
+    /// Removes and returns the oldest sample, if any.
+    pub fn evict_oldest(&mut self) -> Option<T> {
+        let sample = self.arrivals.pop_front()?;
+        let (sample, _) = self.sorted.take(&(sample, self.oldest))?;
+        self.oldest += 1;
+
+        Some(sample)
+    }
+
+    /// Returns the median sample, the lower one of the two middle samples for an even count.
+    pub fn median(&self) -> Option<&T> {
+        self.quantile(0.5)
+    }
+
+    /// Returns the sample at quantile q by the nearest rank method, q being clamped to 0..=1.
+    ///
+    /// Quantile 0 is the smallest sample and 1 the largest.
+    pub fn quantile(&self, q: f64) -> Option<&T> {
+        let rank = (q.clamp(0.0, 1.0) * self.len() as f64).ceil() as usize;
+        self.select(rank.saturating_sub(1))
+    }
+
+    /// Returns the sample at index in ascending order, if any.
+    pub fn select(&self, index: usize) -> Option<&T> {
+        get_at(&self.sorted.root, index).map(|node| &node.value.0)
+    }
+
+    /// Gets an iterator over the k largest samples, largest first.
+    pub fn top_k(&self, k: usize) -> impl Iterator<Item = &'_ T> + '_ {
+        self.sorted
+            .range::<(T, u64), _>(..)
+            .rev()
+            .take(k)
+            .map(|(sample, _)| sample)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sorted copy of the last window samples.
+    fn sorted_window(samples: &[i8], window: usize) -> Vec<i8> {
+        let mut sorted = samples[samples.len().saturating_sub(window)..].to_vec();
+        sorted.sort();
+        sorted
+    }
+
+    #[quickcheck]
+    fn window_parity(samples: Vec<i8>, window: u8, q: u8) -> bool {
+        let window = window as usize % 16 + 1;
+        let q = q as f64 / 255.0;
+        let mut tracker = SlidingQuantile::new(window);
+
+        samples.iter().enumerate().all(|(i, &sample)| {
+            let evicted = tracker.push(sample);
+            let expected_evicted = i.checked_sub(window).map(|oldest| samples[oldest]);
+            let sorted = sorted_window(&samples[..=i], window);
+            let rank = ((q * sorted.len() as f64).ceil() as usize).max(1);
+
+            evicted == expected_evicted
+                && tracker.len() == sorted.len()
+                && tracker.median() == Some(&sorted[sorted.len().div_ceil(2) - 1])
+                && tracker.quantile(q) == Some(&sorted[rank - 1])
+                && tracker.quantile(0.0) == sorted.first()
+                && tracker.quantile(1.0) == sorted.last()
+                && tracker.top_k(3).eq(sorted.iter().rev().take(3))
+        })
+    }
+
+    #[quickcheck]
+    fn evict_parity(samples: Vec<i8>, evictions: u8) -> bool {
+        let mut tracker = SlidingQuantile::new(usize::MAX);
+        samples.iter().for_each(|&sample| {
+            tracker.push(sample);
+        });
+
+        let evictions = evictions as usize;
+        let evicted = (0..evictions).map_while(|_| tracker.evict_oldest()).collect::<Vec<_>>();
+        let remaining = &samples[evictions.min(samples.len())..];
+        let mut sorted = remaining.to_vec();
+        sorted.sort();
+
+        evicted == samples[..evictions.min(samples.len())]
+            && tracker.sorted.validate().is_ok()
+            && (0..sorted.len()).all(|i| tracker.select(i) == Some(&sorted[i]))
+            && tracker.select(sorted.len()).is_none()
+    }
+
+    #[test]
+    fn rolling_median() {
+        let mut tracker = SlidingQuantile::new(3);
+        assert_eq!(tracker.median(), None);
+
+        for sample in [5, 1, 9] {
+            tracker.push(sample);
+        }
+        assert_eq!(tracker.median(), Some(&5));
+
+        assert_eq!(tracker.push(2), Some(5));
+        assert_eq!(tracker.median(), Some(&2));
+        assert_eq!(tracker.top_k(5).collect::<Vec<_>>(), [&9, &2, &1]);
+        assert_eq!(SlidingQuantile::new(0).push(1), Some(1));
+    }
+}
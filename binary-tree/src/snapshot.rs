@@ -0,0 +1,400 @@
+/*
+    Binary snapshot format for AVLTreeSet.
+
+    Layout, all integers little endian:
+        magic       4 bytes  "AVLS"
+        version     u16
+        count       u64
+        elements    count times: u32 length followed by the encoded element,
+                    in strictly ascending order
+        checksum    u64      FNV-1a of every byte before it
+
+    Elements are stored sorted, so loading builds the tree directly in O(n).
+*/
+
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use crate::{AVLTreeSet, BalancePolicy};
+
+const MAGIC: [u8; 4] = *b"AVLS";
+const VERSION: u16 = 1;
+
+/// Encoding of a single element in a snapshot.
+pub trait ElementCodec: Sized {
+    /// Appends the encoded element to out.
+    fn encode(&self, out: &mut Vec<u8>);
+
+    /// Decodes an element from exactly the bytes written by encode.
+    /// Returns None if the bytes are not a valid element.
+    fn decode(bytes: &[u8]) -> Option<Self>;
+}
+
+macro_rules! impl_element_codec_for_int {
+    ($($int:ty),*) => {
+        $(
+            impl ElementCodec for $int {
+                fn encode(&self, out: &mut Vec<u8>) {
+                    out.extend_from_slice(&self.to_le_bytes());
+                }
+
+                fn decode(bytes: &[u8]) -> Option<Self> {
+                    Some(Self::from_le_bytes(bytes.try_into().ok()?))
+                }
+            }
+        )*
+    };
+}
+
+impl_element_codec_for_int!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128);
+
+impl ElementCodec for usize {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (*self as u64).encode(out);
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        u64::decode(bytes)?.try_into().ok()
+    }
+}
+
+impl ElementCodec for isize {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (*self as i64).encode(out);
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        i64::decode(bytes)?.try_into().ok()
+    }
+}
+
+impl ElementCodec for String {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(self.as_bytes());
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        String::from_utf8(bytes.to_vec()).ok()
+    }
+}
+
+impl ElementCodec for Vec<u8> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(self);
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        Some(bytes.to_vec())
+    }
+}
+
+#[derive(Debug)]
+/// Reasons a snapshot could not be saved or loaded.
+pub enum SnapshotError {
+    /// Reading or writing failed.
+    Io(io::Error),
+    /// The data does not start with the snapshot magic bytes.
+    BadMagic,
+    /// The snapshot was written by an unknown version of the format.
+    UnsupportedVersion(u16),
+    /// The data ended before the snapshot did.
+    Truncated,
+    /// The stored checksum does not match the data.
+    ChecksumMismatch { stored: u64, computed: u64 },
+    /// The element at index could not be decoded.
+    Decode { index: u64 },
+    /// The element at index is not greater than the element before it.
+    Unsorted { index: u64 },
+    /// The element at index encodes to more bytes than the format can hold.
+    ElementTooLarge { index: u64 },
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::Io(err) => write!(f, "snapshot io error: {}", err),
+            SnapshotError::BadMagic => write!(f, "not a snapshot, bad magic bytes"),
+            SnapshotError::UnsupportedVersion(version) => {
+                write!(f, "unsupported snapshot version {}", version)
+            }
+            SnapshotError::Truncated => write!(f, "snapshot is truncated"),
+            SnapshotError::ChecksumMismatch { stored, computed } => write!(
+                f,
+                "snapshot checksum {:#018x} does not match computed {:#018x}",
+                stored, computed
+            ),
+            SnapshotError::Decode { index } => write!(f, "element {} could not be decoded", index),
+            SnapshotError::Unsorted { index } => write!(f, "element {} is out of order", index),
+            SnapshotError::ElementTooLarge { index } => write!(f, "element {} is too large", index),
+        }
+    }
+}
+
+impl Error for SnapshotError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SnapshotError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SnapshotError {
+    fn from(err: io::Error) -> Self {
+        if err.kind() == io::ErrorKind::UnexpectedEof {
+            SnapshotError::Truncated
+        } else {
+            SnapshotError::Io(err)
+        }
+    }
+}
+
+/// 64 bit FNV-1a hash, used as a checksum.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Checksum(u64);
+
+impl Checksum {
+    pub(crate) fn new() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+
+    pub(crate) fn update(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+
+    pub(crate) fn value(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Writer that keeps a checksum of everything written through it.
+struct ChecksumWriter<W> {
+    inner: W,
+    checksum: Checksum,
+}
+
+impl<W: Write> ChecksumWriter<W> {
+    fn write_all(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.checksum.update(bytes);
+        self.inner.write_all(bytes)
+    }
+}
+
+/// Reader that keeps a checksum of everything read through it.
+struct ChecksumReader<R> {
+    inner: R,
+    checksum: Checksum,
+}
+
+impl<R: Read> ChecksumReader<R> {
+    fn read_exact<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        let mut bytes = [0; N];
+        self.inner.read_exact(&mut bytes)?;
+        self.checksum.update(&bytes);
+        Ok(bytes)
+    }
+
+    /// Reads len bytes without trusting len for the allocation, as it may be corrupt.
+    fn read_vec(&mut self, len: usize) -> io::Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        (&mut self.inner).take(len as u64).read_to_end(&mut bytes)?;
+        if bytes.len() < len {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        self.checksum.update(&bytes);
+        Ok(bytes)
+    }
+}
+
+impl<T: Ord + ElementCodec, P: BalancePolicy> AVLTreeSet<T, P> {
+    /// Writes the set to writer in the snapshot format.
+    pub fn save_to<W: Write>(&self, writer: W) -> Result<(), SnapshotError> {
+        let mut writer = ChecksumWriter {
+            inner: writer,
+            checksum: Checksum::new(),
+        };
+
+        writer.write_all(&MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        writer.write_all(&(self.len() as u64).to_le_bytes())?;
+
+        let mut bytes = Vec::new();
+        for (index, value) in self.iter().enumerate() {
+            bytes.clear();
+            value.encode(&mut bytes);
+
+            let len = u32::try_from(bytes.len())
+                .map_err(|_| SnapshotError::ElementTooLarge { index: index as u64 })?;
+            writer.write_all(&len.to_le_bytes())?;
+            writer.write_all(&bytes)?;
+        }
+
+        let checksum = writer.checksum.value();
+        writer.inner.write_all(&checksum.to_le_bytes())?;
+        writer.inner.flush()?;
+
+        Ok(())
+    }
+
+    /// Reads a set written by save_to from reader.
+    ///
+    /// Builds the tree in O(n) with `AvlPolicy`. Corrupt or truncated data is reported as an error.
+    pub fn load_from<R: Read>(reader: R) -> Result<Self, SnapshotError>
+    where
+        P: Default,
+    {
+        let mut reader = ChecksumReader {
+            inner: reader,
+            checksum: Checksum::new(),
+        };
+
+        if reader.read_exact::<4>()? != MAGIC {
+            return Err(SnapshotError::BadMagic);
+        }
+
+        let version = u16::from_le_bytes(reader.read_exact()?);
+        if version != VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+
+        let count = u64::from_le_bytes(reader.read_exact()?);
+
+        // Do not trust count for the allocation either
+        let mut values: Vec<T> = Vec::with_capacity(count.min(1 << 16) as usize);
+        for index in 0..count {
+            let len = u32::from_le_bytes(reader.read_exact()?);
+            let bytes = reader.read_vec(len as usize)?;
+            let value = T::decode(&bytes).ok_or(SnapshotError::Decode { index })?;
+
+            if values.last().is_some_and(|last| last >= &value) {
+                return Err(SnapshotError::Unsorted { index });
+            }
+
+            values.push(value);
+        }
+
+        let computed = reader.checksum.value();
+        let mut stored = [0; 8];
+        reader.inner.read_exact(&mut stored)?;
+        let stored = u64::from_le_bytes(stored);
+        if stored != computed {
+            return Err(SnapshotError::ChecksumMismatch { stored, computed });
+        }
+
+        Ok(Self::from_sorted(values))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{TreapPolicy, WeightBalancedPolicy};
+
+    fn snapshot<T: Ord + ElementCodec, P: BalancePolicy>(set: &AVLTreeSet<T, P>) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        set.save_to(&mut bytes).unwrap();
+        bytes
+    }
+
+    /// Loads what set saves into a set with the same policy.
+    fn round_trip<T: Ord + ElementCodec, P: BalancePolicy + Default>(set: AVLTreeSet<T, P>) -> bool {
+        let loaded = AVLTreeSet::<T, P>::load_from(&snapshot(&set)[..]).unwrap();
+
+        loaded.validate().is_ok() && loaded.iter().eq(set.iter())
+    }
+
+    #[quickcheck]
+    fn round_trip_integers(set: AVLTreeSet<i64>) -> bool {
+        round_trip(set)
+    }
+
+    #[quickcheck]
+    fn weight_balanced_round_trip_integers(set: AVLTreeSet<i64, WeightBalancedPolicy>) -> bool {
+        round_trip(set)
+    }
+
+    #[quickcheck]
+    fn treap_round_trip_integers(set: AVLTreeSet<i64, TreapPolicy>) -> bool {
+        round_trip(set)
+    }
+
+    #[quickcheck]
+    fn round_trip_strings(set: AVLTreeSet<String>) -> bool {
+        round_trip(set)
+    }
+
+    #[quickcheck]
+    fn weight_balanced_round_trip_strings(set: AVLTreeSet<String, WeightBalancedPolicy>) -> bool {
+        round_trip(set)
+    }
+
+    #[quickcheck]
+    fn treap_round_trip_strings(set: AVLTreeSet<String, TreapPolicy>) -> bool {
+        round_trip(set)
+    }
+
+    #[quickcheck]
+    fn truncated_is_error(set: AVLTreeSet<u16>, cut: usize) -> bool {
+        let bytes = snapshot(&set);
+        let cut = cut % bytes.len();
+
+        matches!(
+            AVLTreeSet::<u16>::load_from(&bytes[..cut]),
+            Err(SnapshotError::Truncated)
+        )
+    }
+
+    #[quickcheck]
+    fn corrupt_is_error(set: AVLTreeSet<u16>, position: usize, flip: u8) -> bool {
+        let mut bytes = snapshot(&set);
+        let position = position % bytes.len();
+        bytes[position] ^= flip.max(1);
+
+        AVLTreeSet::<u16>::load_from(&bytes[..]).is_err()
+    }
+
+    #[test]
+    fn typed_errors() {
+        let set = (0..10u32).collect::<AVLTreeSet<_>>();
+        let bytes = snapshot(&set);
+
+        let mut bad_magic = bytes.clone();
+        bad_magic[0] = b'X';
+        assert!(matches!(
+            AVLTreeSet::<u32>::load_from(&bad_magic[..]),
+            Err(SnapshotError::BadMagic)
+        ));
+
+        let mut bad_version = bytes.clone();
+        bad_version[4] = 9;
+        assert!(matches!(
+            AVLTreeSet::<u32>::load_from(&bad_version[..]),
+            Err(SnapshotError::UnsupportedVersion(9))
+        ));
+
+        // Second element is 1, make it 0 so it equals the first
+        let mut unsorted = bytes.clone();
+        unsorted[14 + 8 + 4] = 0;
+        assert!(matches!(
+            AVLTreeSet::<u32>::load_from(&unsorted[..]),
+            Err(SnapshotError::Unsorted { index: 1 })
+        ));
+
+        // Wrong element width for u32
+        assert!(matches!(
+            AVLTreeSet::<u64>::load_from(&bytes[..]),
+            Err(SnapshotError::Decode { index: 0 })
+        ));
+
+        let mut bad_checksum = bytes;
+        *bad_checksum.last_mut().unwrap() ^= 1;
+        assert!(matches!(
+            AVLTreeSet::<u32>::load_from(&bad_checksum[..]),
+            Err(SnapshotError::ChecksumMismatch { .. })
+        ));
+    }
+}
@@ -0,0 +1,213 @@
+/*
+    Rebalancing instrumentation and tree shape statistics.
+    Only compiled with the `stats` feature.
+
+    Counters are kept per thread and cover every tree used on that thread,
+    so reset them before the workload to measure.
+*/
+
+use std::cell::Cell;
+
+use crate::{AVLTreeSet, BalancePolicy};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+/// Work done by the trees on the current thread since the last reset.
+pub struct RebalanceCounters {
+    /// Values added by `AVLTreeSet::insert`.
+    pub inserts: u64,
+    /// Sum of the depths at which inserted values were placed, counting the root as 1.
+    pub insert_path_length: u64,
+    /// Values removed by `AVLTreeSet::take`.
+    pub takes: u64,
+    /// Sum of the depths at which taken values were found, counting the root as 1.
+    pub take_path_length: u64,
+    /// Element comparisons made while searching in `insert` and `take`.
+    pub comparisons: u64,
+    /// Rebalances fixed with one rotation.
+    pub single_rotations: u64,
+    /// Rebalances fixed with two rotations.
+    pub double_rotations: u64,
+    /// Calls to `rotate_left`.
+    pub left_rotations: u64,
+    /// Calls to `rotate_right`.
+    pub right_rotations: u64,
+}
+
+impl RebalanceCounters {
+    /// Average depth of an insert, or 0 if there were none.
+    pub fn average_insert_path(&self) -> f64 {
+        average(self.insert_path_length, self.inserts)
+    }
+
+    /// Average depth of a take, or 0 if there were none.
+    pub fn average_take_path(&self) -> f64 {
+        average(self.take_path_length, self.takes)
+    }
+
+    /// Rotations per insert and take.
+    pub fn rotations_per_update(&self) -> f64 {
+        average(self.left_rotations + self.right_rotations, self.inserts + self.takes)
+    }
+}
+
+thread_local! {
+    static COUNTERS: Cell<RebalanceCounters> = Cell::new(RebalanceCounters::default());
+}
+
+/// Returns the counters for the current thread.
+pub fn counters() -> RebalanceCounters {
+    COUNTERS.with(|counters| counters.get())
+}
+
+/// Resets the counters for the current thread to zero.
+pub fn reset() {
+    COUNTERS.with(|counters| counters.set(RebalanceCounters::default()));
+}
+
+/// Updates the counters for the current thread.
+pub(crate) fn record(update: impl FnOnce(&mut RebalanceCounters)) {
+    COUNTERS.with(|counters| {
+        let mut current = counters.get();
+        update(&mut current);
+        counters.set(current);
+    });
+}
+
+#[derive(Debug, PartialEq, Clone, Default)]
+/// The shape of an AVLTreeSet.
+pub struct TreeStats {
+    /// Number of nodes.
+    pub len: usize,
+    /// Number of levels, 0 for an empty tree.
+    pub height: usize,
+    /// Average depth of a node, counting the root as 1.
+    pub average_depth: f64,
+    /// Number of nodes on each level, the root being on level 0.
+    pub depth_histogram: Vec<usize>,
+}
+
+impl TreeStats {
+    /// Largest height an AVL Tree with this many nodes can have, 1.44·log2(n + 2) − 0.328.
+    pub fn height_bound(&self) -> f64 {
+        1.4405 * ((self.len + 2) as f64).log2() - 0.3277
+    }
+
+    /// Smallest height any binary tree with this many nodes can have, log2(n + 1).
+    pub fn optimal_height(&self) -> usize {
+        (self.len + 1).next_power_of_two().trailing_zeros() as usize
+    }
+
+    /// Height relative to the AVL bound. Close to 1 means a worst case shaped tree.
+    pub fn height_ratio(&self) -> f64 {
+        if self.len == 0 {
+            0.0
+        } else {
+            self.height as f64 / self.height_bound()
+        }
+    }
+}
+
+impl<T: Ord, P: BalancePolicy> AVLTreeSet<T, P> {
+    /// Reports the shape of the tree.
+    pub fn stats(&self) -> TreeStats {
+        let mut depth_histogram = Vec::new();
+        let mut levels = vec![&self.root];
+
+        // Walk the tree level by level
+        while !levels.is_empty() {
+            let nodes = levels.into_iter().flatten().collect::<Vec<_>>();
+            if nodes.is_empty() {
+                break;
+            }
+
+            depth_histogram.push(nodes.len());
+            levels = nodes
+                .into_iter()
+                .flat_map(|node| [&node.left, &node.right])
+                .collect();
+        }
+
+        let total_depth = depth_histogram
+            .iter()
+            .enumerate()
+            .map(|(level, &count)| (level + 1) * count)
+            .sum::<usize>();
+
+        TreeStats {
+            len: self.len(),
+            height: depth_histogram.len(),
+            average_depth: average(total_depth as u64, self.len() as u64),
+            depth_histogram,
+        }
+    }
+}
+
+fn average(total: u64, count: u64) -> f64 {
+    if count == 0 {
+        0.0
+    } else {
+        total as f64 / count as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[quickcheck]
+    fn stats_match_tree(xs: Vec<u16>) -> bool {
+        let set = xs.into_iter().collect::<AVLTreeSet<_>>();
+        let stats = set.stats();
+
+        stats.len == set.len()
+            && stats.depth_histogram.iter().sum::<usize>() == set.len()
+            && stats.height == set.root.as_ref().map_or(0, |node| node.height)
+            && stats.height as f64 <= stats.height_bound().max(0.0) + 1e-9
+            && stats.height >= stats.optimal_height()
+    }
+
+    #[test]
+    fn sequential_inserts_rotate() {
+        reset();
+        let set = (0..1024).collect::<AVLTreeSet<u32>>();
+        let counters = counters();
+
+        assert_eq!(counters.inserts, 1024);
+        assert_eq!(counters.takes, 0);
+        // Ascending inserts only ever lean right, so every fix is one left rotation
+        assert_eq!(counters.double_rotations, 0);
+        assert_eq!(counters.right_rotations, 0);
+        assert_eq!(counters.left_rotations, counters.single_rotations);
+        assert!(counters.single_rotations > 0);
+        assert_eq!(counters.comparisons, counters.insert_path_length - 1024);
+
+        let stats = set.stats();
+        assert_eq!(stats.height, 11);
+        assert_eq!(stats.depth_histogram[0], 1);
+    }
+
+    #[test]
+    fn counts_takes() {
+        let mut set = (0..100).collect::<AVLTreeSet<u32>>();
+        reset();
+
+        for i in 0..50 {
+            set.take(&i);
+        }
+        set.take(&1000);
+
+        let counters = counters();
+        assert_eq!(counters.takes, 50);
+        assert_eq!(counters.inserts, 0);
+        assert!(counters.take_path_length >= 50);
+        assert!(counters.comparisons >= counters.take_path_length);
+    }
+
+    #[test]
+    fn empty_tree() {
+        let stats = AVLTreeSet::<u8>::new().stats();
+
+        assert_eq!(stats, TreeStats::default());
+        assert_eq!(stats.height_ratio(), 0.0);
+    }
+}